@@ -0,0 +1,607 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::file::{FlushMode, FlushPolicy};
+use crate::filter::LevelFilters;
+use crate::journald::JournaldConfig;
+use crate::queue::OverflowPolicy;
+use crate::rotation::{Compression, Retention, RotationInterval};
+use crate::sink::{ColorMode, ConsoleStream, Sink, SinkConfig, SinkKind};
+use crate::syslog::SyslogConfig;
+use crate::template::{self, Template};
+use crate::timestamp::{Clock, TimestampFormat, Timezone};
+use crate::units::{ByteSize, HumanDuration};
+use crate::watch;
+use crate::{CustomLevel, LogLevel, PluginError};
+
+#[derive(Serialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LogFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<LogFormat>()
+            .map_err(|_| serde::de::Error::custom(format!("Invalid log format: {:?}", s)))
+    }
+}
+
+fn default_log_file() -> String {
+    "default.log".to_string()
+}
+
+fn default_log_to_file() -> bool {
+    true
+}
+
+fn default_log_to_console() -> bool {
+    true
+}
+
+fn default_config_poll_interval_ms() -> u64 {
+    1000
+}
+
+pub fn default_minimum_log_level() -> LogLevel {
+    LogLevel::Info
+}
+
+fn default_max_log_file_size() -> u64 {
+    10 * 1024 * 1024 // 10MB
+}
+
+fn default_max_log_file_count() -> u32 {
+    5
+}
+
+fn default_enable_log_rotation() -> bool {
+    true
+}
+
+fn default_log_to_file_colored() -> bool {
+    false
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_flush_buffer_bytes() -> usize {
+    8 * 1024
+}
+
+fn default_async_queue_capacity() -> usize {
+    1024
+}
+
+fn default_log_format() -> LogFormat {
+    LogFormat::Text
+}
+
+/// An entry of `custom_levels`.
+#[derive(Serialize, Deserialize)]
+pub struct CustomLevelConfig {
+    pub name: String,
+
+    /// Position in the severity order on the OpenTelemetry scale (1-24), e.g.
+    /// 10 sorts between INFO (9) and WARN (13). A level with the same
+    /// severity as a built-in one is filtered and routed like it.
+    pub severity: u8,
+
+    #[serde(default)]
+    pub color: Option<String>,
+
+    /// Defaults to the syslog severity of the built-in level in the same range.
+    #[serde(default)]
+    pub syslog_severity: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub app_name: String,
+
+    #[serde(default = "default_log_file")]
+    pub log_file: String,
+
+    #[serde(default = "default_log_to_file")]
+    pub log_to_file: bool,
+
+    #[serde(default = "default_log_to_console")]
+    pub log_to_console: bool,
+
+    #[serde(default = "default_minimum_log_level")]
+    pub minimum_log_level: LogLevel,
+
+    #[serde(default = "default_max_log_file_size")]
+    pub max_log_file_size: u64,
+
+    #[serde(default = "default_max_log_file_count")]
+    pub max_log_file_count: u32,
+
+    #[serde(default = "default_enable_log_rotation")]
+    pub enable_log_rotation: bool,
+
+    #[serde(default = "default_log_to_file_colored")]
+    pub log_to_file_colored: bool,
+
+    #[serde(default = "default_log_format")]
+    pub log_format: LogFormat,
+
+    #[serde(default)]
+    pub console_format: Option<String>,
+
+    #[serde(default)]
+    pub console_stream: ConsoleStream,
+
+    #[serde(default)]
+    pub console_color: ColorMode,
+
+    #[serde(default)]
+    pub file_format: Option<String>,
+
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+
+    #[serde(default)]
+    pub timezone: Timezone,
+
+    #[serde(default)]
+    pub rotation_interval: Option<RotationInterval>,
+
+    #[serde(default)]
+    pub rotation_compression: Compression,
+
+    #[serde(default)]
+    pub max_archive_age: Option<HumanDuration>,
+
+    #[serde(default)]
+    pub max_total_log_size: Option<ByteSize>,
+
+    #[serde(default)]
+    pub flush_policy: FlushMode,
+
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+
+    #[serde(default = "default_flush_buffer_bytes")]
+    pub flush_buffer_bytes: usize,
+
+    #[serde(default)]
+    pub async_mode: bool,
+
+    #[serde(default = "default_async_queue_capacity")]
+    pub async_queue_capacity: usize,
+
+    #[serde(default)]
+    pub async_overflow: OverflowPolicy,
+
+    #[serde(default)]
+    pub config_file: Option<String>,
+
+    #[serde(default = "default_config_poll_interval_ms")]
+    pub config_poll_interval_ms: u64,
+
+    /// Minimum levels by target, e.g. `{"deploy": "debug", "deploy -> db": "warn", "*": "info"}`.
+    #[serde(default)]
+    pub level_filters: BTreeMap<String, LogLevel>,
+
+    #[serde(default)]
+    pub custom_levels: Vec<CustomLevelConfig>,
+
+    /// Also sends every record to syslog when set.
+    #[serde(default)]
+    pub syslog: Option<SyslogConfig>,
+
+    /// Also sends every record to the systemd journal when set.
+    #[serde(default)]
+    pub journald: Option<JournaldConfig>,
+
+    /// Replaces the console, file, syslog and journald settings above when set.
+    #[serde(default)]
+    pub sinks: Option<Vec<SinkConfig>>,
+}
+
+/// A validated [`PluginConfig`], swapped in as a whole so that readers always
+/// see a consistent configuration.
+pub struct Settings {
+    /// The JSON passed to `initialize` (without the `config_file` layer),
+    /// used as the base for `reconfigure` and config file reloads.
+    pub source: serde_json::Value,
+    pub app_name: String,
+    pub sinks: Vec<Sink>,
+    pub minimum_log_level: LogLevel,
+    pub level_filters: LevelFilters,
+    pub custom_levels: Vec<LogLevel>,
+    pub max_log_file_size: u64,
+    pub max_log_file_count: u32,
+    pub enable_log_rotation: bool,
+    pub clock: Clock,
+    pub rotation_interval: Option<RotationInterval>,
+    pub rotation_compression: Compression,
+    pub retention: Retention,
+    pub flush_policy: FlushPolicy,
+    pub async_mode: bool,
+    pub async_queue_capacity: usize,
+    pub async_overflow: OverflowPolicy,
+    pub config_file: Option<String>,
+    pub config_poll_interval: Duration,
+    /// The `XTOMATE_LOGGER_*` variables that overrode the config, with their values.
+    pub env_overrides: Vec<(&'static str, String)>,
+    /// The `XTOMATE_LOGGER_*` variables that were set but had no effect.
+    pub ignored_env_overrides: Vec<&'static str>,
+}
+
+/// Environment variables that override a config field, and the field each one
+/// sets.
+const ENV_OVERRIDES: [(&str, &str); 4] = [
+    ("XTOMATE_LOGGER_LEVEL", "minimum_log_level"),
+    ("XTOMATE_LOGGER_FILE", "log_file"),
+    ("XTOMATE_LOGGER_FORMAT", "log_format"),
+    ("XTOMATE_LOGGER_CONSOLE", "log_to_console"),
+];
+
+/// Fields that only shape the sinks derived from the flat settings, and so
+/// mean nothing once `sinks` is set.
+const FLAT_SINK_FIELDS: [&str; 3] = ["log_file", "log_format", "log_to_console"];
+
+/// The `XTOMATE_LOGGER_*` variables found by [`apply_env_overrides`].
+#[derive(Debug, Default, PartialEq)]
+struct EnvOverrides {
+    used: Vec<(&'static str, String)>,
+    ignored: Vec<&'static str>,
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Applies the `XTOMATE_LOGGER_*` variables that `env` finds set to `config`.
+/// Empty variables are treated as unset. With `sinks` configured, the
+/// variables for the flat sink settings are ignored rather than applied.
+fn apply_env_overrides(
+    config: &mut serde_json::Value,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<EnvOverrides, PluginError> {
+    let has_sinks = config.get("sinks").is_some_and(|sinks| !sinks.is_null());
+    let mut patch = serde_json::Map::new();
+    let mut overrides = EnvOverrides::default();
+    for (var, field) in ENV_OVERRIDES {
+        let Some(value) = env(var).filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        if has_sinks && FLAT_SINK_FIELDS.contains(&field) {
+            overrides.ignored.push(var);
+            continue;
+        }
+        let json = if field == "log_to_console" {
+            let enabled = parse_bool(&value).ok_or_else(|| {
+                PluginError::InvalidFormat(format!("{}: expected a boolean, got {:?}", var, value))
+            })?;
+            serde_json::Value::Bool(enabled)
+        } else {
+            serde_json::Value::String(value.clone())
+        };
+        patch.insert(field.to_string(), json);
+        overrides.used.push((var, value));
+    }
+    merge_patch(config, &serde_json::Value::Object(patch));
+    Ok(overrides)
+}
+
+impl Settings {
+    /// Builds settings from the config passed to `initialize`. Later layers
+    /// win, field by field:
+    ///
+    /// 1. built-in defaults
+    /// 2. the JSON passed to `initialize` (and `reconfigure` patches)
+    /// 3. the contents of `config_file`, if set
+    /// 4. `XTOMATE_LOGGER_LEVEL`, `XTOMATE_LOGGER_FILE`,
+    ///    `XTOMATE_LOGGER_FORMAT` and `XTOMATE_LOGGER_CONSOLE`; the last three
+    ///    are ignored when `sinks` is set
+    pub fn from_value(source: serde_json::Value) -> Result<Self, PluginError> {
+        let mut effective = source.clone();
+        if let Some(path) = source.get("config_file").and_then(|p| p.as_str()) {
+            let file = watch::load_config_file(Path::new(path))
+                .map_err(|e| PluginError::InvalidFormat(format!("config_file {}: {}", path, e)))?;
+            merge_patch(&mut effective, &file);
+        }
+        let env_overrides = apply_env_overrides(&mut effective, |var| std::env::var(var).ok())?;
+
+        let config: PluginConfig = crate::from_value_with_level(
+            effective,
+            "minimum_log_level",
+            PluginError::InvalidConfig,
+        )?;
+        let mut settings = Self::from_config(config, source)?;
+        settings.env_overrides = env_overrides.used;
+        settings.ignored_env_overrides = env_overrides.ignored;
+        Ok(settings)
+    }
+
+    /// Paths of the file sinks.
+    pub fn log_files(&self) -> impl Iterator<Item = &str> {
+        self.sinks.iter().filter_map(Sink::file_path)
+    }
+
+    /// Whether any sink takes a record at `level` for `target` (`app` or
+    /// `app -> sub_app`).
+    pub fn enabled(&self, level: &LogLevel, target: &str) -> bool {
+        self.sinks
+            .iter()
+            .any(|sink| sink.enabled(level, target, self))
+    }
+
+    fn from_config(config: PluginConfig, source: serde_json::Value) -> Result<Self, PluginError> {
+        let parse_format = |name: &str, format: &Option<String>| {
+            Template::parse(format.as_deref().unwrap_or(template::DEFAULT_FORMAT))
+                .map_err(|e| PluginError::InvalidFormat(format!("{}: {}", name, e)))
+        };
+        let custom_levels = config
+            .custom_levels
+            .iter()
+            .map(custom_level)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PluginError::InvalidFormat(format!("custom_levels: {}", e)))?;
+        for (i, level) in custom_levels.iter().enumerate() {
+            if custom_levels[..i]
+                .iter()
+                .any(|l| l.to_string() == level.to_string())
+            {
+                return Err(PluginError::InvalidFormat(format!(
+                    "custom_levels: {} is declared twice",
+                    level
+                )));
+            }
+        }
+
+        let sinks = match config.sinks {
+            Some(sinks) => sinks
+                .into_iter()
+                .enumerate()
+                .map(|(i, sink)| {
+                    Sink::from_config(sink)
+                        .map_err(|e| PluginError::InvalidFormat(format!("sinks[{}]: {}", i, e)))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => {
+                let mut sinks = Vec::new();
+                if config.log_to_console {
+                    let template = parse_format("console_format", &config.console_format)?;
+                    let kind = SinkKind::Console {
+                        stream: config.console_stream,
+                    };
+                    let color = config.console_color;
+                    sinks.push(Sink::new(kind, LogFormat::Text, template, color));
+                }
+                if config.log_to_file {
+                    let template = parse_format("file_format", &config.file_format)?;
+                    let kind = SinkKind::File {
+                        path: config.log_file,
+                    };
+                    let color = match config.log_to_file_colored {
+                        true => ColorMode::Always,
+                        false => ColorMode::Never,
+                    };
+                    sinks.push(Sink::new(kind, config.log_format, template, color));
+                }
+                let remote = config
+                    .syslog
+                    .map(SinkKind::Syslog)
+                    .into_iter()
+                    .chain(config.journald.map(SinkKind::Journald));
+                for kind in remote {
+                    let template = Template::default();
+                    sinks.push(Sink::new(kind, LogFormat::Text, template, ColorMode::Never));
+                }
+                sinks
+            }
+        };
+
+        // `max_log_file_count` includes the active file; 0 disables the limit.
+        let retention = Retention {
+            max_count: match config.max_log_file_count {
+                0 => None,
+                n => Some(n as usize - 1),
+            },
+            max_age: config.max_archive_age.map(|age| age.0),
+            max_total_size: config.max_total_log_size.map(|size| size.0),
+        };
+
+        // The watcher would otherwise poll the config file without pause.
+        if config.config_poll_interval_ms == 0 {
+            return Err(PluginError::InvalidFormat(
+                "config_poll_interval_ms must be greater than 0".to_string(),
+            ));
+        }
+
+        let flush_policy = match config.flush_policy {
+            FlushMode::EveryLine => FlushPolicy::EveryLine,
+            FlushMode::IntervalMs => {
+                FlushPolicy::Interval(std::time::Duration::from_millis(config.flush_interval_ms))
+            }
+            FlushMode::BufferBytes => FlushPolicy::BufferBytes(config.flush_buffer_bytes),
+        };
+
+        Ok(Settings {
+            source,
+            app_name: config.app_name,
+            sinks,
+            minimum_log_level: config.minimum_log_level,
+            level_filters: LevelFilters::new(&config.level_filters),
+            custom_levels,
+            max_log_file_size: config.max_log_file_size,
+            max_log_file_count: config.max_log_file_count,
+            enable_log_rotation: config.enable_log_rotation,
+            clock: Clock {
+                format: config.timestamp_format,
+                timezone: config.timezone,
+            },
+            rotation_interval: config.rotation_interval,
+            rotation_compression: config.rotation_compression,
+            retention,
+            flush_policy,
+            async_mode: config.async_mode,
+            async_queue_capacity: config.async_queue_capacity,
+            async_overflow: config.async_overflow,
+            config_file: config.config_file,
+            config_poll_interval: Duration::from_millis(config.config_poll_interval_ms),
+            env_overrides: Vec::new(),
+            ignored_env_overrides: Vec::new(),
+        })
+    }
+}
+
+fn custom_level(config: &CustomLevelConfig) -> Result<LogLevel, String> {
+    let name = config.name.trim().to_uppercase();
+    if name.is_empty() || name.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(format!("invalid level name {:?}", config.name));
+    }
+    // Aliases such as `notice` may be redefined, the canonical names may not.
+    if ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"].contains(&name.as_str()) {
+        return Err(format!("{} is a built-in level", name));
+    }
+    let builtin = LogLevel::from_otel_severity(config.severity)
+        .ok_or_else(|| format!("{}: severity must be between 1 and 24", name))?;
+    let color = match &config.color {
+        Some(color) => Some(
+            color
+                .parse()
+                .map_err(|_| format!("{}: unknown color {:?}", name, color))?,
+        ),
+        None => None,
+    };
+    let syslog_severity = match config.syslog_severity {
+        Some(severity @ 0..=7) => severity,
+        Some(_) => return Err(format!("{}: syslog_severity must be between 0 and 7", name)),
+        None => builtin.syslog_severity(),
+    };
+
+    Ok(LogLevel::Custom(Arc::new(CustomLevel {
+        name,
+        severity: config.severity,
+        color,
+        syslog_severity,
+    })))
+}
+
+/// Applies a JSON merge patch (RFC 7396): objects are merged recursively and
+/// `null` removes a key.
+pub fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn env(var: &str) -> Option<String> {
+        match var {
+            "XTOMATE_LOGGER_LEVEL" => Some("debug".to_string()),
+            "XTOMATE_LOGGER_FILE" => Some("env.log".to_string()),
+            "XTOMATE_LOGGER_FORMAT" => Some("json".to_string()),
+            "XTOMATE_LOGGER_CONSOLE" => Some("off".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn env_overrides_the_flat_sink_settings() {
+        let mut config = json!({"app_name": "test", "log_file": "app.log"});
+        let overrides = apply_env_overrides(&mut config, env).unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "app_name": "test",
+                "minimum_log_level": "debug",
+                "log_file": "env.log",
+                "log_format": "json",
+                "log_to_console": false,
+            })
+        );
+        assert_eq!(overrides.used.len(), 4);
+        assert!(overrides.ignored.is_empty());
+
+        let mut config = json!({"app_name": "test", "log_file": "app.log"});
+        let overrides = apply_env_overrides(&mut config, |var| match var {
+            "XTOMATE_LOGGER_CONSOLE" => Some("maybe".to_string()),
+            _ => None,
+        });
+        assert!(overrides.is_err());
+    }
+
+    #[test]
+    fn env_does_not_override_configured_sinks() {
+        let sinks = json!([{"type": "file", "path": "app.log"}]);
+        let mut config = json!({"app_name": "test", "sinks": sinks});
+        let overrides = apply_env_overrides(&mut config, env).unwrap();
+        assert_eq!(
+            config,
+            json!({"app_name": "test", "sinks": sinks, "minimum_log_level": "debug"})
+        );
+        assert_eq!(
+            overrides,
+            EnvOverrides {
+                used: vec![("XTOMATE_LOGGER_LEVEL", "debug".to_string())],
+                ignored: vec![
+                    "XTOMATE_LOGGER_FILE",
+                    "XTOMATE_LOGGER_FORMAT",
+                    "XTOMATE_LOGGER_CONSOLE"
+                ],
+            }
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use serde_json::{Map, Value};
+
+/// Flattens nested objects and arrays into dotted keys, e.g.
+/// `{"job": {"id": 4}, "hosts": ["a", "b"]}` becomes `job.id`, `hosts.0`, `hosts.1`.
+pub fn flatten(fields: &Map<String, Value>) -> Vec<(String, Value)> {
+    let mut out = Vec::new();
+    for (key, value) in fields {
+        flatten_into(key.clone(), value, &mut out);
+    }
+    out
+}
+
+fn flatten_into(prefix: String, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten_into(format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        Value::Array(items) if !items.is_empty() => {
+            for (index, value) in items.iter().enumerate() {
+                flatten_into(format!("{}.{}", prefix, index), value, out);
+            }
+        }
+        _ => out.push((prefix, value.clone())),
+    }
+}
+
+/// Renders a single scalar as it appears in a `key=value` pair. Strings are
+/// left bare unless they would be ambiguous, in which case they are quoted.
+pub fn render_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => {
+            let needs_quotes = s.is_empty()
+                || s.chars()
+                    .any(|c| c.is_whitespace() || c.is_control() || c == '=' || c == '"');
+            if needs_quotes {
+                value.to_string()
+            } else {
+                s.clone()
+            }
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Renders fields as space separated `key=value` pairs.
+pub fn render_pairs(fields: &Map<String, Value>) -> String {
+    flatten(fields)
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, render_value(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Which flush policy is selected in the config. The parameters live in
+/// `flush_interval_ms` and `flush_buffer_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushMode {
+    #[default]
+    EveryLine,
+    IntervalMs,
+    BufferBytes,
+}
+
+impl std::fmt::Display for FlushMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FlushMode::EveryLine => write!(f, "every_line"),
+            FlushMode::IntervalMs => write!(f, "interval_ms"),
+            FlushMode::BufferBytes => write!(f, "buffer_bytes"),
+        }
+    }
+}
+
+impl FromStr for FlushMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "every_line" => Ok(FlushMode::EveryLine),
+            "interval_ms" => Ok(FlushMode::IntervalMs),
+            "buffer_bytes" => Ok(FlushMode::BufferBytes),
+            _ => Err(format!("invalid flush policy: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for FlushMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FlushMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<FlushMode>().map_err(serde::de::Error::custom)
+    }
+}
+
+/// When buffered lines are written through to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// Flush after every line.
+    #[default]
+    EveryLine,
+    /// Flush on the first write after the interval has elapsed since the last
+    /// flush. An [`IntervalFlusher`] flushes lines buffered while idle.
+    Interval(Duration),
+    /// Flush once at least the given number of bytes is buffered.
+    BufferBytes(usize),
+}
+
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// The log file, kept open across calls with its size tracked in memory.
+pub struct LogFile {
+    writer: BufWriter<File>,
+    size: u64,
+    policy: FlushPolicy,
+    last_flush: Instant,
+}
+
+impl LogFile {
+    pub fn open(path: &Path, policy: FlushPolicy) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile {
+            writer: BufWriter::with_capacity(DEFAULT_BUFFER_SIZE, file),
+            size,
+            policy,
+            last_flush: Instant::now(),
+        })
+    }
+
+    /// Size of the file including lines that are still buffered.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        self.size += line.len() as u64 + 1;
+
+        match self.policy {
+            FlushPolicy::EveryLine => self.flush(),
+            FlushPolicy::Interval(interval) if self.last_flush.elapsed() >= interval => {
+                self.flush()
+            }
+            FlushPolicy::BufferBytes(bytes) if self.writer.buffer().len() >= bytes => self.flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Switches to `policy`, flushing whatever is buffered first.
+    pub fn set_policy(&mut self, policy: FlushPolicy) -> io::Result<()> {
+        self.policy = policy;
+        self.flush()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        self.writer.flush()
+    }
+
+    /// Flushes if anything is buffered.
+    pub fn flush_buffered(&mut self) -> io::Result<()> {
+        if self.writer.buffer().is_empty() {
+            return Ok(());
+        }
+        self.flush()
+    }
+}
+
+/// Calls `on_tick` every `interval` on a background thread, so that lines
+/// buffered under [`FlushPolicy::Interval`] reach disk within one interval
+/// even when nothing else is logged. The thread exits once the flusher is
+/// dropped or stopped, or `on_tick` returns false.
+pub struct IntervalFlusher {
+    interval: Duration,
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl IntervalFlusher {
+    pub fn spawn(interval: Duration, on_tick: impl Fn() -> bool + Send + 'static) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            if !on_tick() {
+                return;
+            }
+        });
+        IntervalFlusher {
+            interval,
+            stop,
+            thread,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Stops the thread and waits for a flush in progress to finish.
+    pub fn stop(self) {
+        drop(self.stop);
+        let _ = self.thread.join();
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use crate::LogLevel;
+
+/// Per-target minimum levels from `level_filters`.
+///
+/// A target is an app name, optionally followed by sub-app names joined with
+/// `" -> "`. A directive applies to its own target and everything below it,
+/// and the most specific (longest) matching directive wins. `"*"` matches
+/// every target.
+#[derive(Debug, Clone, Default)]
+pub struct LevelFilters {
+    /// Normalized targets, longest first.
+    directives: Vec<(String, LogLevel)>,
+    fallback: Option<LogLevel>,
+}
+
+/// Collapses the spacing around `->` so `"deploy->db"` and `"deploy -> db"`
+/// name the same target.
+fn normalize(target: &str) -> String {
+    target
+        .split("->")
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+impl LevelFilters {
+    pub fn new(filters: &BTreeMap<String, LogLevel>) -> Self {
+        let mut directives = Vec::new();
+        let mut fallback = None;
+        for (target, level) in filters {
+            match target.trim() {
+                "*" => fallback = Some(level.clone()),
+                target => directives.push((normalize(target), level.clone())),
+            }
+        }
+        directives.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        LevelFilters {
+            directives,
+            fallback,
+        }
+    }
+
+    /// The level of the most specific directive matching `target`, if any.
+    pub fn level_for(&self, target: &str) -> Option<&LogLevel> {
+        self.directives
+            .iter()
+            .find(|(directive, _)| {
+                target
+                    .strip_prefix(directive.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(" -> "))
+            })
+            .map(|(_, level)| level)
+            .or(self.fallback.as_ref())
+    }
+}
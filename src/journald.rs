@@ -0,0 +1,412 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+use crate::fields;
+use crate::syslog::SEND_TIMEOUT;
+use crate::template::Record;
+
+fn default_socket() -> String {
+    "/run/systemd/journal/socket".to_string()
+}
+
+/// The `journald` section of the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournaldConfig {
+    #[serde(default = "default_socket")]
+    pub socket: String,
+}
+
+impl JournaldConfig {
+    /// Describes the destination for error messages.
+    pub fn describe(&self) -> String {
+        format!("journald {}", self.socket)
+    }
+}
+
+/// Fields set by the sink itself; record fields with these names are dropped.
+const RESERVED: [&str; 4] = ["MESSAGE", "PRIORITY", "SYSLOG_IDENTIFIER", "SUB_APP"];
+
+/// Turns a field key into a journal field name: upper-case letters, digits and
+/// underscores, not starting with a digit or underscore, at most 64 bytes.
+fn field_name(key: &str) -> Option<String> {
+    let name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .skip_while(|c| *c == '_' || c.is_ascii_digit())
+        .take(64)
+        .collect();
+    if name.is_empty() || RESERVED.contains(&name.as_str()) {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Appends one field in the native protocol: `NAME=value\n`, or the binary
+/// form with a little-endian length for values containing newlines.
+fn push_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+fn encode(record: &Record) -> Vec<u8> {
+    let mut entry = Vec::new();
+    push_field(&mut entry, "MESSAGE", record.message);
+    push_field(
+        &mut entry,
+        "PRIORITY",
+        &record.level.syslog_severity().to_string(),
+    );
+    push_field(&mut entry, "SYSLOG_IDENTIFIER", record.app);
+    if let Some(sub_app) = record.sub_app {
+        push_field(&mut entry, "SUB_APP", sub_app);
+    }
+    for (key, value) in fields::flatten(record.fields) {
+        let Some(name) = field_name(&key) else {
+            continue;
+        };
+        let value = match value {
+            serde_json::Value::String(s) => s,
+            value => value.to_string(),
+        };
+        push_field(&mut entry, &name, &value);
+    }
+    entry
+}
+
+/// A connection to the journal's native socket.
+pub struct JournaldSink {
+    config: JournaldConfig,
+    socket: UnixDatagram,
+}
+
+fn open(path: &str) -> io::Result<UnixDatagram> {
+    let socket = UnixDatagram::unbound()?;
+    socket.connect(path)?;
+    socket.set_write_timeout(Some(SEND_TIMEOUT))?;
+    Ok(socket)
+}
+
+impl JournaldSink {
+    pub fn connect(config: &JournaldConfig) -> io::Result<Self> {
+        Ok(JournaldSink {
+            config: config.clone(),
+            socket: open(&config.socket)?,
+        })
+    }
+
+    pub fn config(&self) -> &JournaldConfig {
+        &self.config
+    }
+
+    pub fn send(&mut self, record: &Record) -> io::Result<()> {
+        let entry = encode(record);
+        match self.send_entry(&entry) {
+            // journald restarted and bound a new socket at the same path.
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ECONNREFUSED | libc::ENOTCONN)) => {
+                self.socket = open(&self.config.socket)?;
+                self.send_entry(&entry)
+            }
+            result => result,
+        }
+    }
+
+    fn send_entry(&self, entry: &[u8]) -> io::Result<()> {
+        match self.socket.send(entry) {
+            Ok(_) => Ok(()),
+            // Too large for a datagram: hand the entry over in a sealed memfd.
+            Err(e) if e.raw_os_error() == Some(libc::EMSGSIZE) => self.send_memfd(entry),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send_memfd(&self, entry: &[u8]) -> io::Result<()> {
+        use std::fs::File;
+        use std::io::Write;
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let fd = unsafe {
+            libc::memfd_create(
+                c"journal-entry".as_ptr(),
+                libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(entry)?;
+        let seals =
+            libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL;
+        if unsafe { libc::fcntl(fd, libc::F_ADD_SEALS, seals) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // An empty datagram carrying only the descriptor.
+        let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<libc::c_int>() as u32) } as usize;
+        let mut control = vec![0u8; space];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = space as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<libc::c_int>() as u32) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>(), fd);
+        }
+        if unsafe { libc::sendmsg(self.socket.as_raw_fd(), &msg, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn send_memfd(&self, _entry: &[u8]) -> io::Result<()> {
+        Err(io::Error::from_raw_os_error(libc::EMSGSIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+    use crate::LogLevel;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+
+    fn record<'a>(
+        level: &'a LogLevel,
+        sub_app: Option<&'a str>,
+        message: &'a str,
+        fields: &'a serde_json::Map<String, Value>,
+    ) -> Record<'a> {
+        Record {
+            timestamp: "",
+            level,
+            app: "deploy",
+            sub_app,
+            target: "",
+            message,
+            fields,
+            seq: 1,
+        }
+    }
+
+    /// A stand-in for the journal socket.
+    fn journal(name: &str) -> (UnixDatagram, JournaldSink) {
+        let path = temp_dir(name).join("socket");
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let config = JournaldConfig {
+            socket: path.to_str().unwrap().to_string(),
+        };
+        (server, JournaldSink::connect(&config).unwrap())
+    }
+
+    #[test]
+    fn sends_native_protocol_entries() {
+        let (server, mut sink) = journal("journald-entry");
+        let fields = json!({
+            "job id": 42,
+            "1st": "a",
+            "_private": true,
+            "message": "dropped",
+            "nested": {"key": "v"},
+        });
+        let fields = fields.as_object().unwrap();
+        sink.send(&record(&LogLevel::Warn, Some("db"), "disk low", fields))
+            .unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            "MESSAGE=disk low\n\
+             PRIORITY=4\n\
+             SYSLOG_IDENTIFIER=deploy\n\
+             SUB_APP=db\n\
+             JOB_ID=42\n\
+             ST=a\n\
+             PRIVATE=true\n\
+             NESTED_KEY=v\n"
+        );
+
+        sink.send(&record(
+            &LogLevel::Trace,
+            None,
+            "quiet",
+            &serde_json::Map::new(),
+        ))
+        .unwrap();
+        let len = server.recv(&mut buf).unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            "MESSAGE=quiet\nPRIORITY=7\nSYSLOG_IDENTIFIER=deploy\n"
+        );
+    }
+
+    #[test]
+    fn reconnects_after_the_journal_restarts() {
+        let (server, mut sink) = journal("journald-restart");
+        let path = server.local_addr().unwrap();
+        let path = path.as_pathname().unwrap().to_path_buf();
+        let empty = serde_json::Map::new();
+        sink.send(&record(&LogLevel::Info, None, "one", &empty))
+            .unwrap();
+
+        drop(server);
+        std::fs::remove_file(&path).unwrap();
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        sink.send(&record(&LogLevel::Info, None, "two", &empty))
+            .unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(std::str::from_utf8(&buf[..len])
+            .unwrap()
+            .starts_with("MESSAGE=two\n"));
+    }
+
+    #[test]
+    fn send_times_out_when_the_journal_stops_reading() {
+        let (_server, mut sink) = journal("journald-timeout");
+        let empty = serde_json::Map::new();
+        let started = std::time::Instant::now();
+        let error = loop {
+            if let Err(e) = sink.send(&record(&LogLevel::Info, None, "x", &empty)) {
+                break e;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "never timed out"
+            );
+        };
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock, "{:?}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn multi_line_values_are_length_prefixed() {
+        let (server, mut sink) = journal("journald-multiline");
+        let fields = json!({"trace": "a\nb"});
+        let fields = fields.as_object().unwrap();
+        sink.send(&record(&LogLevel::Error, None, "two\nlines", fields))
+            .unwrap();
+
+        let mut buf = vec![0u8; 4096];
+        let len = server.recv(&mut buf).unwrap();
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"MESSAGE\n");
+        expected.extend_from_slice(&9u64.to_le_bytes());
+        expected.extend_from_slice(b"two\nlines\n");
+        expected.extend_from_slice(b"PRIORITY=3\nSYSLOG_IDENTIFIER=deploy\n");
+        expected.extend_from_slice(b"TRACE\n");
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(&buf[..len], expected.as_slice());
+    }
+
+    #[test]
+    fn field_names() {
+        assert_eq!(field_name("job id").as_deref(), Some("JOB_ID"));
+        assert_eq!(
+            field_name("http.status-code").as_deref(),
+            Some("HTTP_STATUS_CODE")
+        );
+        assert_eq!(field_name("__9lives").as_deref(), Some("LIVES"));
+        assert_eq!(field_name(&"k".repeat(100)).map(|n| n.len()), Some(64));
+        assert_eq!(field_name("sub_app"), None);
+        assert_eq!(field_name("Priority"), None);
+        assert_eq!(field_name("123"), None);
+        assert_eq!(field_name("ü"), None);
+    }
+
+    /// Receives one datagram and the descriptor passed with it.
+    #[cfg(target_os = "linux")]
+    fn recv_fd(socket: &UnixDatagram) -> (usize, Option<std::os::fd::OwnedFd>) {
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let mut data = [0u8; 16];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+        let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<libc::c_int>() as u32) };
+        let mut control = vec![0u8; space as usize];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control.len() as _;
+
+        let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+        assert!(len >= 0, "{}", io::Error::last_os_error());
+        let cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+        if cmsg.is_null() {
+            return (len as usize, None);
+        }
+        unsafe {
+            assert_eq!((*cmsg).cmsg_level, libc::SOL_SOCKET);
+            assert_eq!((*cmsg).cmsg_type, libc::SCM_RIGHTS);
+            let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>());
+            (len as usize, Some(std::os::fd::OwnedFd::from_raw_fd(fd)))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn oversized_entries_are_passed_in_a_sealed_memfd() {
+        use std::io::{Read, Seek};
+        use std::os::fd::AsRawFd;
+
+        let (server, mut sink) = journal("journald-memfd");
+        let message = "x".repeat(1024 * 1024);
+        sink.send(&record(
+            &LogLevel::Info,
+            None,
+            &message,
+            &serde_json::Map::new(),
+        ))
+        .unwrap();
+
+        let (len, fd) = recv_fd(&server);
+        assert_eq!(len, 0);
+        let fd = fd.expect("no descriptor was passed");
+        let seals = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GET_SEALS) };
+        assert_ne!(seals & libc::F_SEAL_WRITE, 0);
+        assert_ne!(seals & libc::F_SEAL_SEAL, 0);
+
+        let mut file = std::fs::File::from(fd);
+        file.rewind().unwrap();
+        let mut entry = String::new();
+        file.read_to_string(&mut entry).unwrap();
+        assert_eq!(
+            entry,
+            format!(
+                "MESSAGE={}\nPRIORITY=6\nSYSLOG_IDENTIFIER=deploy\n",
+                message
+            )
+        );
+    }
+}
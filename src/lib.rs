@@ -1,28 +1,312 @@
+mod config;
+mod fields;
+mod file;
+mod filter;
+mod journald;
+mod logger;
+mod queue;
+mod rotation;
+mod sink;
+mod syslog;
+mod template;
+mod timestamp;
+mod units;
+mod watch;
+
 use colored::*;
+use config::Settings;
 use libc::c_char;
-use serde::{Deserialize, Deserializer, Serialize};
-use serde_json;
-use std::ffi::CStr;
-use std::fs::OpenOptions;
-use std::io::Write;
+use logger::Logger;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
 use std::str::FromStr;
-use std::sync::{LazyLock, Mutex};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// Return codes of the exported functions.
+///
+/// | Code | Meaning                                              |
+/// |------|------------------------------------------------------|
+/// | 0    | Success                                              |
+/// | -1   | Writing to the log file failed                       |
+/// | -2   | A null pointer was passed                            |
+/// | -3   | The passed string is not valid UTF-8                 |
+/// | -4   | The configuration JSON is invalid                    |
+/// | -5   | The input JSON is invalid                            |
+/// | -6   | Unknown log level                                    |
+/// | -7   | The log file could not be opened                     |
+/// | -8   | Log rotation failed                                  |
+/// | -9   | `execute` was called before `initialize`             |
+/// | -10  | An unexpected internal error (panic) was caught      |
+/// | -11  | Unknown logger handle                                |
+/// | -12  | Sending to a syslog or journald sink failed          |
+pub mod error_code {
+    pub const OK: i32 = 0;
+    pub const FILE_WRITE: i32 = -1;
+    pub const NULL_POINTER: i32 = -2;
+    pub const INVALID_UTF8: i32 = -3;
+    pub const INVALID_CONFIG: i32 = -4;
+    pub const INVALID_INPUT: i32 = -5;
+    pub const UNKNOWN_LEVEL: i32 = -6;
+    pub const FILE_OPEN: i32 = -7;
+    pub const ROTATION: i32 = -8;
+    pub const NOT_INITIALIZED: i32 = -9;
+    pub const INTERNAL: i32 = -10;
+    pub const INVALID_HANDLE: i32 = -11;
+    pub const SINK_WRITE: i32 = -12;
+}
+
+#[derive(Debug)]
+enum PluginError {
+    NullPointer,
+    InvalidUtf8(std::str::Utf8Error),
+    InvalidConfig(serde_json::Error),
+    InvalidFormat(String),
+    InvalidInput(serde_json::Error),
+    UnknownLevel(String),
+    FileOpen(String, std::io::Error),
+    FileWrite(String, std::io::Error),
+    Rotation(String, std::io::Error),
+    SinkWrite(String, std::io::Error),
+    NotInitialized,
+    InvalidHandle(i64),
+    Internal(String),
+}
+
+impl PluginError {
+    fn code(&self) -> i32 {
+        match self {
+            PluginError::NullPointer => error_code::NULL_POINTER,
+            PluginError::InvalidUtf8(_) => error_code::INVALID_UTF8,
+            PluginError::InvalidConfig(_) => error_code::INVALID_CONFIG,
+            PluginError::InvalidFormat(_) => error_code::INVALID_CONFIG,
+            PluginError::InvalidInput(_) => error_code::INVALID_INPUT,
+            PluginError::UnknownLevel(_) => error_code::UNKNOWN_LEVEL,
+            PluginError::FileOpen(..) => error_code::FILE_OPEN,
+            PluginError::FileWrite(..) => error_code::FILE_WRITE,
+            PluginError::Rotation(..) => error_code::ROTATION,
+            PluginError::SinkWrite(..) => error_code::SINK_WRITE,
+            PluginError::NotInitialized => error_code::NOT_INITIALIZED,
+            PluginError::InvalidHandle(_) => error_code::INVALID_HANDLE,
+            PluginError::Internal(_) => error_code::INTERNAL,
+        }
+    }
+}
+
+impl std::fmt::Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PluginError::NullPointer => write!(f, "null pointer passed"),
+            PluginError::InvalidUtf8(e) => write!(f, "invalid UTF-8: {}", e),
+            PluginError::InvalidConfig(e) => write!(f, "invalid config: {}", e),
+            PluginError::InvalidFormat(e) => write!(f, "invalid config: {}", e),
+            PluginError::InvalidInput(e) => write!(f, "invalid input: {}", e),
+            PluginError::UnknownLevel(level) => write!(f, "unknown log level: {:?}", level),
+            PluginError::FileOpen(path, e) => write!(f, "log file {}: {}", path, e),
+            PluginError::FileWrite(path, e) => {
+                write!(f, "failed to write to log file {}: {}", path, e)
+            }
+            PluginError::Rotation(path, e) => {
+                write!(f, "failed to rotate log file {}: {}", path, e)
+            }
+            PluginError::SinkWrite(sink, e) => write!(f, "failed to write to {}: {}", sink, e),
+            PluginError::NotInitialized => write!(f, "plugin is not initialized"),
+            PluginError::InvalidHandle(handle) => write!(f, "unknown logger handle: {}", handle),
+            PluginError::Internal(msg) => write!(f, "internal error: {}", msg),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(i32, String)>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, converting both errors and panics into a return code so that
+/// nothing unwinds across the FFI boundary.
+fn ffi_guard<F: FnOnce() -> Result<(), PluginError>>(f: F) -> i32 {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let msg = if let Some(s) = payload.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+        Err(PluginError::Internal(msg))
+    });
+
+    match result {
+        Ok(()) => error_code::OK,
+        Err(e) => {
+            let code = e.code();
+            let message = e.to_string();
+            eprintln!("logger: {}", message);
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some((code, message)));
+            code
+        }
+    }
+}
+
+/// Borrows a C string as `&str`.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a valid nul-terminated string.
+unsafe fn str_from_ptr<'a>(ptr: *const c_char) -> Result<&'a str, PluginError> {
+    if ptr.is_null() {
+        return Err(PluginError::NullPointer);
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(PluginError::InvalidUtf8)
+}
 
-#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+/// Deserializes a JSON value, reporting a bad `level_key` as `UnknownLevel`
+/// rather than as a generic parse error.
+fn from_value_with_level<T: serde::de::DeserializeOwned>(
+    value: serde_json::Value,
+    level_key: &str,
+    invalid: fn(serde_json::Error) -> PluginError,
+) -> Result<T, PluginError> {
+    let level = match value.get(level_key) {
+        Some(serde_json::Value::String(s)) => Some(s.clone()),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    };
+    if let Some(level) = level {
+        level
+            .parse::<LogLevel>()
+            .map_err(|_| PluginError::UnknownLevel(level.clone()))?;
+    }
+    serde_json::from_value(value).map_err(invalid)
+}
+
+/// Severity of a record. Levels are compared with [`LogLevel::is_at_least`],
+/// by their OpenTelemetry severity number alone.
+///
+/// Besides the names below, levels can be given as the syslog names `emerg`,
+/// `alert`, `crit`, `err`, `warning` and `notice`, as syslog severities
+/// (`0`-`7` as numbers or numeric strings, or `syslog:N`) and as
+/// OpenTelemetry severity numbers written `otel:N` (`1`-`24`). Bare numbers
+/// are always syslog severities, so `1` is FATAL and `otel:1` is TRACE.
+/// `Custom` levels come from the `custom_levels` config and are only accepted
+/// by `execute`.
+#[derive(Debug, Clone)]
 enum LogLevel {
+    Trace,
     Debug,
     Info,
     Warn,
     Error,
+    Fatal,
+    Custom(Arc<CustomLevel>),
+}
+
+/// A level declared in `custom_levels`.
+#[derive(Debug)]
+struct CustomLevel {
+    /// Upper-cased, as displayed.
+    name: String,
+    /// Position in the severity order, on the OpenTelemetry scale (1-24).
+    severity: u8,
+    color: Option<Color>,
+    syslog_severity: u8,
 }
 
+impl PartialEq for LogLevel {
+    fn eq(&self, other: &Self) -> bool {
+        self.otel_severity() == other.otel_severity() && self.to_string() == other.to_string()
+    }
+}
+
+impl Eq for LogLevel {}
+
 impl std::fmt::Display for LogLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
+            LogLevel::Trace => write!(f, "TRACE"),
             LogLevel::Info => write!(f, "INFO"),
             LogLevel::Debug => write!(f, "DEBUG"),
             LogLevel::Warn => write!(f, "WARN"),
             LogLevel::Error => write!(f, "ERROR"),
+            LogLevel::Fatal => write!(f, "FATAL"),
+            LogLevel::Custom(level) => write!(f, "{}", level.name),
+        }
+    }
+}
+
+impl LogLevel {
+    /// Whether a record at this level passes `minimum`. Only the severity
+    /// counts, so a custom level with the same severity as `minimum` passes.
+    fn is_at_least(&self, minimum: &LogLevel) -> bool {
+        self.otel_severity() >= minimum.otel_severity()
+    }
+
+    fn colorize(&self, s: String) -> ColoredString {
+        match self {
+            LogLevel::Trace => s.magenta(),
+            LogLevel::Debug => s.blue(),
+            LogLevel::Info => s.green(),
+            LogLevel::Warn => s.yellow(),
+            LogLevel::Error => s.red(),
+            LogLevel::Fatal => s.red().bold(),
+            LogLevel::Custom(level) => match level.color {
+                Some(color) => s.color(color),
+                None => s.normal(),
+            },
+        }
+    }
+
+    /// The syslog severity (RFC 5424): 0 is emergency, 7 is debug.
+    fn syslog_severity(&self) -> u8 {
+        match self {
+            LogLevel::Trace | LogLevel::Debug => 7,
+            LogLevel::Info => 6,
+            LogLevel::Warn => 4,
+            LogLevel::Error => 3,
+            LogLevel::Fatal => 2,
+            LogLevel::Custom(level) => level.syslog_severity,
+        }
+    }
+
+    fn from_syslog_severity(severity: u8) -> Option<Self> {
+        match severity {
+            0..=2 => Some(LogLevel::Fatal),
+            3 => Some(LogLevel::Error),
+            4 => Some(LogLevel::Warn),
+            5 | 6 => Some(LogLevel::Info),
+            7 => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    /// The OpenTelemetry severity number: the first number of each level's
+    /// range (`TRACE` 1-4, `DEBUG` 5-8, ... `FATAL` 21-24).
+    fn otel_severity(&self) -> u8 {
+        match self {
+            LogLevel::Trace => 1,
+            LogLevel::Debug => 5,
+            LogLevel::Info => 9,
+            LogLevel::Warn => 13,
+            LogLevel::Error => 17,
+            LogLevel::Fatal => 21,
+            LogLevel::Custom(level) => level.severity,
+        }
+    }
+
+    /// The built-in level whose OpenTelemetry range contains `severity`.
+    fn from_otel_severity(severity: u8) -> Option<Self> {
+        match severity {
+            1..=4 => Some(LogLevel::Trace),
+            5..=8 => Some(LogLevel::Debug),
+            9..=12 => Some(LogLevel::Info),
+            13..=16 => Some(LogLevel::Warn),
+            17..=20 => Some(LogLevel::Error),
+            21..=24 => Some(LogLevel::Fatal),
+            _ => None,
         }
     }
 }
@@ -31,11 +315,22 @@ impl FromStr for LogLevel {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "info" => Ok(LogLevel::Info),
+        let s = s.trim().to_lowercase();
+        if let Some(severity) = s.strip_prefix("otel:") {
+            let severity = severity.trim().parse::<u8>().map_err(|_| ())?;
+            return LogLevel::from_otel_severity(severity).ok_or(());
+        }
+        let numeric = s.strip_prefix("syslog:").unwrap_or(&s);
+        if let Ok(severity) = numeric.trim().parse::<u8>() {
+            return LogLevel::from_syslog_severity(severity).ok_or(());
+        }
+        match s.as_str() {
+            "trace" => Ok(LogLevel::Trace),
             "debug" => Ok(LogLevel::Debug),
-            "warn" => Ok(LogLevel::Warn),
-            "error" => Ok(LogLevel::Error),
+            "info" | "notice" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "error" | "err" => Ok(LogLevel::Error),
+            "fatal" | "critical" | "crit" | "alert" | "emerg" => Ok(LogLevel::Fatal),
             _ => Err(()),
         }
     }
@@ -46,236 +341,862 @@ impl<'de> Deserialize<'de> for LogLevel {
     where
         D: Deserializer<'de>,
     {
-        let s: String = String::deserialize(deserializer)?;
-        s.to_lowercase()
-            .parse::<LogLevel>()
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Name(String),
+            Severity(u64),
+        }
+
+        let s = match Raw::deserialize(deserializer)? {
+            Raw::Name(s) => s,
+            Raw::Severity(n) => n.to_string(),
+        };
+        s.parse::<LogLevel>()
             .map_err(|e| serde::de::Error::custom(format!("Invalid log level: {:?}", e)))
     }
 }
 
-fn default_log_file() -> String {
-    "default.log".to_string()
+impl Serialize for LogLevel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
-fn default_log_to_file() -> bool {
-    true
-}
+#[derive(Serialize, Deserialize)]
+struct ExecutionInput {
+    message: String,
 
-fn default_log_to_console() -> bool {
-    true
+    #[serde(default = "config::default_minimum_log_level")]
+    level: LogLevel,
+
+    app_name: Option<String>,
+
+    sub_app_name: Option<String>,
+
+    #[serde(default)]
+    fields: serde_json::Map<String, serde_json::Value>,
 }
 
-fn default_minimum_log_level() -> LogLevel {
-    LogLevel::Info
+static DEFAULT_LOGGER: LazyLock<Arc<Logger>> = LazyLock::new(|| Arc::new(Logger::default()));
+static LOGGERS: LazyLock<Mutex<HashMap<i64, Arc<Logger>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_HANDLE: AtomicI64 = AtomicI64::new(1);
+
+fn parse_config(config_str: &str) -> Result<Settings, PluginError> {
+    let value = serde_json::from_str(config_str).map_err(PluginError::InvalidConfig)?;
+    Settings::from_value(value)
 }
 
-fn default_max_log_file_size() -> u64 {
-    10 * 1024 * 1024 // 10MB
+/// Merges a partial JSON config into the running configuration of `logger`.
+fn reconfigure_logger(logger: &Arc<Logger>, patch_str: &str) -> Result<(), PluginError> {
+    let patch: serde_json::Value =
+        serde_json::from_str(patch_str).map_err(PluginError::InvalidConfig)?;
+    logger.reconfigure(|current| {
+        let mut merged = current.source.clone();
+        config::merge_patch(&mut merged, &patch);
+        Settings::from_value(merged)
+    })
 }
 
-fn default_max_log_file_count() -> u32 {
-    5
+/// Parses an `execute` input, resolving `level` against the custom levels
+/// configured on `logger` before the built-in names.
+fn parse_input(logger: &Logger, input_str: &str) -> Result<ExecutionInput, PluginError> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(input_str).map_err(PluginError::InvalidInput)?;
+    let custom = value
+        .get("level")
+        .and_then(|level| level.as_str())
+        .and_then(|name| logger.custom_level(name));
+    if custom.is_some() {
+        if let Some(input) = value.as_object_mut() {
+            input.remove("level");
+        }
+    }
+
+    let mut input: ExecutionInput =
+        from_value_with_level(value, "level", PluginError::InvalidInput)?;
+    if let Some(level) = custom {
+        input.level = level;
+    }
+    Ok(input)
 }
 
-fn default_enable_log_rotation() -> bool {
-    true
+fn lookup_logger(handle: i64) -> Result<Arc<Logger>, PluginError> {
+    LOGGERS
+        .lock()
+        .unwrap()
+        .get(&handle)
+        .cloned()
+        .ok_or(PluginError::InvalidHandle(handle))
 }
 
-fn default_log_to_file_colored() -> bool {
-    true
+/// Initializes the default logger from a JSON config. Returns one of [`error_code`].
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn initialize(config: *const c_char) -> i32 {
+    ffi_guard(|| DEFAULT_LOGGER.initialize(parse_config(str_from_ptr(config)?)?))
 }
 
-#[derive(Serialize, Deserialize)]
-struct PluginConfig {
-    app_name: String,
+/// Logs a single JSON-encoded [`ExecutionInput`] through the default logger.
+/// Returns one of [`error_code`].
+///
+/// # Safety
+///
+/// `input` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn execute(input: *const c_char) -> i32 {
+    ffi_guard(|| DEFAULT_LOGGER.execute(parse_input(&DEFAULT_LOGGER, str_from_ptr(input)?)?))
+}
 
-    #[serde(default = "default_log_file")]
-    log_file: String,
+/// Resets the default logger. Returns one of [`error_code`].
+#[no_mangle]
+pub extern "C" fn teardown() -> i32 {
+    ffi_guard(|| DEFAULT_LOGGER.teardown())
+}
 
-    #[serde(default = "default_log_to_file")]
-    log_to_file: bool,
+/// Merges a partial JSON config into the default logger's running
+/// configuration and applies it atomically. Keys set to `null` revert to
+/// their defaults. Returns one of [`error_code`].
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn reconfigure(config: *const c_char) -> i32 {
+    ffi_guard(|| reconfigure_logger(&DEFAULT_LOGGER, str_from_ptr(config)?))
+}
 
-    #[serde(default = "default_log_to_console")]
-    log_to_console: bool,
+/// Returns how many records the default logger discarded because its async
+/// queue was full, since the last `initialize`.
+#[no_mangle]
+pub extern "C" fn dropped_records() -> u64 {
+    DEFAULT_LOGGER.dropped_records()
+}
 
-    #[serde(default = "default_minimum_log_level")]
-    minimum_log_level: LogLevel,
+/// Creates an independent logger from a JSON config. Returns a positive
+/// handle, or a negative [`error_code`] on failure.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn create_logger(config: *const c_char) -> i64 {
+    let mut handle = 0;
+    let code = ffi_guard(|| {
+        let logger = Arc::new(Logger::default());
+        logger.initialize(parse_config(str_from_ptr(config)?)?)?;
+        handle = NEXT_HANDLE.fetch_add(1, Ordering::Relaxed);
+        LOGGERS.lock().unwrap().insert(handle, logger);
+        Ok(())
+    });
+    if code == error_code::OK {
+        handle
+    } else {
+        code as i64
+    }
+}
 
-    #[serde(default = "default_max_log_file_size")]
-    max_log_file_size: u64,
+/// Logs a single JSON-encoded [`ExecutionInput`] through the logger behind
+/// `handle`. Returns one of [`error_code`].
+///
+/// # Safety
+///
+/// `input` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn execute_with(handle: i64, input: *const c_char) -> i32 {
+    ffi_guard(|| {
+        let logger = lookup_logger(handle)?;
+        logger.execute(parse_input(&logger, str_from_ptr(input)?)?)
+    })
+}
 
-    #[serde(default = "default_max_log_file_count")]
-    max_log_file_count: u32,
+/// Like [`reconfigure`], for the logger behind `handle`.
+///
+/// # Safety
+///
+/// `config` must be null or point to a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn reconfigure_with(handle: i64, config: *const c_char) -> i32 {
+    ffi_guard(|| reconfigure_logger(&lookup_logger(handle)?, str_from_ptr(config)?))
+}
 
-    #[serde(default = "default_enable_log_rotation")]
-    enable_log_rotation: bool,
+/// Returns how many records the logger behind `handle` discarded because its
+/// async queue was full, or 0 for an unknown handle.
+#[no_mangle]
+pub extern "C" fn dropped_records_with(handle: i64) -> u64 {
+    // There is no error code to return, so a panic is reported like a failed lookup.
+    panic::catch_unwind(|| lookup_logger(handle).map_or(0, |logger| logger.dropped_records()))
+        .unwrap_or(0)
+}
 
-    #[serde(default = "default_log_to_file_colored")]
-    log_to_file_colored: bool,
+/// Flushes and destroys the logger behind `handle`. Returns one of [`error_code`].
+#[no_mangle]
+pub extern "C" fn destroy_logger(handle: i64) -> i32 {
+    ffi_guard(|| {
+        let logger = LOGGERS
+            .lock()
+            .unwrap()
+            .remove(&handle)
+            .ok_or(PluginError::InvalidHandle(handle))?;
+        logger.teardown()
+    })
 }
 
-#[derive(Serialize, Deserialize)]
-struct ExecutionInput {
-    message: String,
+/// Returns the message of the most recent failure on the calling thread, or
+/// null if nothing has failed yet. If `code` is not null, the matching
+/// [`error_code`] is written to it (`0` when there is no error).
+///
+/// The returned string must be released with [`free_last_error`].
+///
+/// # Safety
+///
+/// `code` must be null or point to a writable `i32`.
+#[no_mangle]
+pub unsafe extern "C" fn last_error(code: *mut i32) -> *mut c_char {
+    let last = LAST_ERROR.with(|last| last.borrow().clone());
+    let (error_code, message) = match last {
+        Some(last) => last,
+        None => (error_code::OK, String::new()),
+    };
 
-    #[serde(default = "default_minimum_log_level")]
-    level: LogLevel,
+    if !code.is_null() {
+        *code = error_code;
+    }
 
-    app_name: Option<String>,
+    if error_code == error_code::OK {
+        return std::ptr::null_mut();
+    }
 
-    sub_app_name: Option<String>,
+    // Messages never contain interior nul bytes in practice; strip them just in case.
+    CString::new(message.replace('\0', ""))
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
 }
 
-static APP_NAME: LazyLock<Mutex<String>> = LazyLock::new(|| Mutex::new(String::new()));
-static LOG_FILE: LazyLock<Mutex<String>> = LazyLock::new(|| Mutex::new(String::new()));
-static LOG_TO_FILE: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
-static LOG_TO_CONSOLE: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
-static MIN_LOG_LEVEL: LazyLock<Mutex<LogLevel>> = LazyLock::new(|| Mutex::new(LogLevel::Info));
-static MAX_LOG_FILE_SIZE: LazyLock<Mutex<u64>> = LazyLock::new(|| Mutex::new(10 * 1024 * 1024)); // 10MB
-static MAX_LOG_FILE_COUNT: LazyLock<Mutex<u32>> = LazyLock::new(|| Mutex::new(5));
-static ENABLE_LOG_ROTATION: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(true));
-static LOG_TO_FILE_COLORED: LazyLock<Mutex<bool>> = LazyLock::new(|| Mutex::new(false));
-
+/// Releases a string returned by [`last_error`].
+///
+/// # Safety
+///
+/// `message` must be null or a pointer obtained from [`last_error`] that has
+/// not been freed yet.
 #[no_mangle]
-pub extern "C" fn initialize(config: *const c_char) -> i32 {
-    let config_cstr = unsafe { CStr::from_ptr(config) };
-    let config_str = config_cstr.to_str().unwrap_or("");
+pub unsafe extern "C" fn free_last_error(message: *mut c_char) {
+    if !message.is_null() {
+        drop(CString::from_raw(message));
+    }
+}
 
-    let config: PluginConfig = serde_json::from_str(config_str).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
 
-    *APP_NAME.lock().unwrap() = config.app_name.clone();
-    *LOG_FILE.lock().unwrap() = config.log_file.clone();
-    *LOG_TO_FILE.lock().unwrap() = config.log_to_file;
-    *LOG_TO_CONSOLE.lock().unwrap() = config.log_to_console;
-    *MIN_LOG_LEVEL.lock().unwrap() = config.minimum_log_level;
-    *MAX_LOG_FILE_SIZE.lock().unwrap() = config.max_log_file_size;
-    *MAX_LOG_FILE_COUNT.lock().unwrap() = config.max_log_file_count;
-    *ENABLE_LOG_ROTATION.lock().unwrap() = config.enable_log_rotation;
-    *LOG_TO_FILE_COLORED.lock().unwrap() = config.log_to_file_colored;
+    /// Serializes tests that use the default logger.
+    static DEFAULT_LOGGER_LOCK: Mutex<()> = Mutex::new(());
 
-    0
-}
+    fn lock_default_logger() -> std::sync::MutexGuard<'static, ()> {
+        DEFAULT_LOGGER_LOCK
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
 
-fn rotate_log_file() {
-    let enable_rotation = *ENABLE_LOG_ROTATION.lock().unwrap();
-    if !enable_rotation {
-        return;
+    /// Creates an empty directory for a test under the system temp directory.
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("logger-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
     }
 
-    let log_file_path = LOG_FILE.lock().unwrap().clone();
-    let max_size = *MAX_LOG_FILE_SIZE.lock().unwrap();
-    let max_count = *MAX_LOG_FILE_COUNT.lock().unwrap();
+    /// A custom level, as declared in `custom_levels`.
+    pub(crate) fn custom(name: &str, severity: u8) -> LogLevel {
+        LogLevel::Custom(Arc::new(CustomLevel {
+            name: name.to_string(),
+            severity,
+            color: None,
+            syslog_severity: 6,
+        }))
+    }
 
-    let log_metadata = std::fs::metadata(&log_file_path);
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
 
-    if let Ok(metadata) = log_metadata {
-        if metadata.len() > max_size {
-            let mut log_files: Vec<String> = std::fs::read_dir(".")
-                .unwrap()
-                .filter_map(|entry| {
-                    entry.ok().and_then(|e| {
-                        let path = e.path();
-                        if path.is_file() && path.file_name()?.to_str()?.starts_with(&log_file_path) {
-                            Some(path.display().to_string())
-                        } else {
-                            None
-                        }
-                    })
-                })
-                .collect();
+    /// A config that writes only to `log_file`.
+    fn file_config(log_file: &std::path::Path, extra: &str) -> CString {
+        let mut config = serde_json::json!({
+            "app_name": "test",
+            "log_to_console": false,
+            "log_file": log_file,
+            "file_format": "{level} {message}",
+        });
+        if !extra.is_empty() {
+            let extra: serde_json::Value = serde_json::from_str(extra).unwrap();
+            config::merge_patch(&mut config, &extra);
+        }
+        c(&config.to_string())
+    }
 
-            log_files.sort();
+    /// Reads back the calling thread's last error through the FFI.
+    fn last() -> (i32, String) {
+        let mut code = 0;
+        let message = unsafe { last_error(&mut code) };
+        if message.is_null() {
+            return (code, String::new());
+        }
+        let text = unsafe { CStr::from_ptr(message) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        unsafe { free_last_error(message) };
+        (code, text)
+    }
 
-            if log_files.len() >= max_count as usize {
-                let file_to_remove = log_files.remove(1);
-                std::fs::remove_file(file_to_remove).unwrap_or(());
-            }
+    fn assert_error(code: i64, expected: i32, message: &str) {
+        assert_eq!(code, expected as i64);
+        let (last_code, last_message) = last();
+        assert_eq!(last_code, expected);
+        assert!(
+            last_message.contains(message),
+            "{:?} does not contain {:?}",
+            last_message,
+            message
+        );
+    }
 
-            let timestamp = chrono::Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
-            let archive_name = format!("{}_{}.log", log_file_path, timestamp);
-            std::fs::rename(&log_file_path, archive_name).unwrap();
+    const INVALID_UTF8: &CStr = c"{\"app_name\": \"\xff\"}";
+
+    #[test]
+    fn initialize_rejects_malformed_configs() {
+        let _guard = lock_default_logger();
+        unsafe {
+            assert_error(
+                initialize(std::ptr::null()) as i64,
+                error_code::NULL_POINTER,
+                "null pointer",
+            );
+            assert_error(
+                initialize(INVALID_UTF8.as_ptr()) as i64,
+                error_code::INVALID_UTF8,
+                "invalid UTF-8",
+            );
+            assert_error(
+                initialize(c("{\"app_name\":").as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "invalid config",
+            );
+            assert_error(
+                initialize(c("{}").as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "app_name",
+            );
+            assert_error(
+                initialize(c(r#"{"app_name":"test","minimum_log_level":"loud"}"#).as_ptr()) as i64,
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level: \"loud\"",
+            );
+            assert_error(
+                initialize(c(r#"{"app_name":"test","timezone":"+1é1"}"#).as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "invalid timezone",
+            );
+            assert_error(
+                initialize(c(r#"{"app_name":"test","console_format":"{nope}"}"#).as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "console_format: unknown placeholder {nope}",
+            );
+            assert_error(
+                initialize(c(r#"{"app_name":"test","config_poll_interval_ms":0}"#).as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "config_poll_interval_ms must be greater than 0",
+            );
         }
     }
-}
 
-#[no_mangle]
-pub extern "C" fn execute(input: *const c_char) -> i32 {
-    let input_cstr = unsafe { CStr::from_ptr(input) };
-    let input_str = input_cstr.to_str().unwrap_or("");
-
-    let input_data: ExecutionInput = serde_json::from_str(input_str).unwrap();
-    let min_level = MIN_LOG_LEVEL.lock().unwrap().clone();
+    #[test]
+    fn execute_rejects_malformed_inputs() {
+        let _guard = lock_default_logger();
+        let dir = temp_dir("execute-errors");
+        let log_file = dir.join("app.log");
+        unsafe {
+            assert_eq!(teardown(), error_code::OK);
+            assert_error(
+                execute(c(r#"{"message":"early"}"#).as_ptr()) as i64,
+                error_code::NOT_INITIALIZED,
+                "not initialized",
+            );
 
-    if input_data.level < min_level {
-        return 0;
+            assert_eq!(
+                initialize(file_config(&log_file, "").as_ptr()),
+                error_code::OK
+            );
+            assert_error(
+                execute(std::ptr::null()) as i64,
+                error_code::NULL_POINTER,
+                "null pointer",
+            );
+            assert_error(
+                execute(INVALID_UTF8.as_ptr()) as i64,
+                error_code::INVALID_UTF8,
+                "invalid UTF-8",
+            );
+            assert_error(
+                execute(c("[1, 2").as_ptr()) as i64,
+                error_code::INVALID_INPUT,
+                "invalid input",
+            );
+            assert_error(
+                execute(c(r#"{"level":"info"}"#).as_ptr()) as i64,
+                error_code::INVALID_INPUT,
+                "message",
+            );
+            assert_error(
+                execute(c(r#"{"message":"m","level":"loud"}"#).as_ptr()) as i64,
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level: \"loud\"",
+            );
+            assert_eq!(execute(c(r#"{"message":"kept"}"#).as_ptr()), error_code::OK);
+            assert_eq!(teardown(), error_code::OK);
+        }
+        assert_eq!(std::fs::read_to_string(&log_file).unwrap(), "INFO kept\n");
     }
 
-    let mut app_name = APP_NAME.lock().unwrap().clone();
-    if let Some(override_app_name) = input_data.app_name {
-        app_name = override_app_name;
+    #[test]
+    fn reconfigure_rejects_malformed_patches() {
+        let _guard = lock_default_logger();
+        let dir = temp_dir("reconfigure-errors");
+        let log_file = dir.join("app.log");
+        unsafe {
+            assert_eq!(teardown(), error_code::OK);
+            assert_error(
+                reconfigure(c(r#"{"minimum_log_level":"debug"}"#).as_ptr()) as i64,
+                error_code::NOT_INITIALIZED,
+                "not initialized",
+            );
+
+            assert_eq!(
+                initialize(file_config(&log_file, "").as_ptr()),
+                error_code::OK
+            );
+            assert_error(
+                reconfigure(std::ptr::null()) as i64,
+                error_code::NULL_POINTER,
+                "null pointer",
+            );
+            assert_error(
+                reconfigure(INVALID_UTF8.as_ptr()) as i64,
+                error_code::INVALID_UTF8,
+                "invalid UTF-8",
+            );
+            assert_error(
+                reconfigure(c("{").as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "invalid config",
+            );
+            assert_error(
+                reconfigure(c(r#"{"minimum_log_level":"loud"}"#).as_ptr()) as i64,
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level: \"loud\"",
+            );
+            assert_error(
+                reconfigure(c(r#"{"file_format":"{level"}"#).as_ptr()) as i64,
+                error_code::INVALID_CONFIG,
+                "file_format: unclosed placeholder",
+            );
+
+            // Rejected patches leave the running configuration in place.
+            assert_eq!(
+                execute(c(r#"{"message":"still here"}"#).as_ptr()),
+                error_code::OK
+            );
+            assert_eq!(teardown(), error_code::OK);
+        }
+        assert_eq!(
+            std::fs::read_to_string(&log_file).unwrap(),
+            "INFO still here\n"
+        );
     }
-    if let Some(sub_app_name) = input_data.sub_app_name {
-        app_name = format!("{} -> {}", app_name, sub_app_name);
+
+    #[test]
+    fn handle_exports_reject_malformed_arguments() {
+        let dir = temp_dir("handle-errors");
+        let log_file = dir.join("app.log");
+        unsafe {
+            assert_error(
+                create_logger(std::ptr::null()),
+                error_code::NULL_POINTER,
+                "null pointer",
+            );
+            assert_error(
+                create_logger(INVALID_UTF8.as_ptr()),
+                error_code::INVALID_UTF8,
+                "invalid UTF-8",
+            );
+            assert_error(
+                create_logger(c("nope").as_ptr()),
+                error_code::INVALID_CONFIG,
+                "invalid config",
+            );
+            assert_error(
+                create_logger(c(r#"{"app_name":"test","minimum_log_level":"loud"}"#).as_ptr()),
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level",
+            );
+            assert_error(
+                create_logger(c(r#"{"app_name":"test","file_format":"}"}"#).as_ptr()),
+                error_code::INVALID_CONFIG,
+                "file_format: unmatched '}'",
+            );
+
+            let handle = create_logger(file_config(&log_file, "").as_ptr());
+            assert!(handle > 0);
+            assert_error(
+                execute_with(handle, std::ptr::null()) as i64,
+                error_code::NULL_POINTER,
+                "null pointer",
+            );
+            assert_error(
+                execute_with(handle, INVALID_UTF8.as_ptr()) as i64,
+                error_code::INVALID_UTF8,
+                "invalid UTF-8",
+            );
+            assert_error(
+                execute_with(handle, c("{\"message\":").as_ptr()) as i64,
+                error_code::INVALID_INPUT,
+                "invalid input",
+            );
+            assert_error(
+                execute_with(handle, c(r#"{"message":"m","level":"loud"}"#).as_ptr()) as i64,
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level",
+            );
+            assert_error(
+                execute_with(-handle, c(r#"{"message":"m"}"#).as_ptr()) as i64,
+                error_code::INVALID_HANDLE,
+                &format!("unknown logger handle: {}", -handle),
+            );
+            assert_error(
+                reconfigure_with(-handle, c("{}").as_ptr()) as i64,
+                error_code::INVALID_HANDLE,
+                "unknown logger handle",
+            );
+            assert_eq!(
+                execute_with(handle, c(r#"{"message":"kept"}"#).as_ptr()),
+                error_code::OK
+            );
+
+            assert_eq!(dropped_records_with(handle), 0);
+            assert_eq!(dropped_records_with(-handle), 0);
+
+            assert_eq!(destroy_logger(handle), error_code::OK);
+            assert_error(
+                destroy_logger(handle) as i64,
+                error_code::INVALID_HANDLE,
+                &format!("unknown logger handle: {}", handle),
+            );
+            assert_error(
+                execute_with(handle, c(r#"{"message":"gone"}"#).as_ptr()) as i64,
+                error_code::INVALID_HANDLE,
+                "unknown logger handle",
+            );
+        }
+        assert_eq!(std::fs::read_to_string(&log_file).unwrap(), "INFO kept\n");
     }
-    let timestamp = chrono::Utc::now().to_string();
-    let timestamp_colored = timestamp.bright_red();
-    let level_colored = match input_data.level {
-        LogLevel::Debug => input_data.level.to_string().blue(),
-        LogLevel::Info => input_data.level.to_string().green(),
-        LogLevel::Warn => input_data.level.to_string().yellow(),
-        LogLevel::Error => input_data.level.to_string().red(),
-    };
 
-    let app_name_colored = app_name.cyan();
-    let message_colored = input_data.message.white();
+    #[test]
+    fn last_error_is_per_thread() {
+        assert_eq!(destroy_logger(0), error_code::INVALID_HANDLE);
+        std::thread::spawn(|| assert_eq!(last(), (error_code::OK, String::new())))
+            .join()
+            .unwrap();
+        assert_eq!(last().0, error_code::INVALID_HANDLE);
+    }
 
-    let log_message = format!(
-        "[{}] [{}] {}: {}",
-        timestamp_colored, level_colored, app_name_colored, message_colored
-    );
+    #[test]
+    fn interval_flush_reaches_disk_while_idle() {
+        let dir = temp_dir("interval-flush");
+        let log_file = dir.join("app.log");
+        let config = file_config(
+            &log_file,
+            r#"{"flush_policy":"interval_ms","flush_interval_ms":50}"#,
+        );
+        unsafe {
+            let handle = create_logger(config.as_ptr());
+            assert!(handle > 0);
+            assert_eq!(
+                execute_with(handle, c(r#"{"message":"one"}"#).as_ptr()),
+                error_code::OK
+            );
+            assert_eq!(
+                execute_with(handle, c(r#"{"message":"two"}"#).as_ptr()),
+                error_code::OK
+            );
 
-    if *LOG_TO_CONSOLE.lock().unwrap() {
-        println!("{}", log_message);
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            assert_eq!(
+                std::fs::read_to_string(&log_file).unwrap(),
+                "INFO one\nINFO two\n"
+            );
+            assert_eq!(destroy_logger(handle), error_code::OK);
+        }
     }
 
-    if *LOG_TO_FILE.lock().unwrap() {
-        rotate_log_file();
+    #[test]
+    fn retention_does_not_race_compression() {
+        for compression in ["gzip", "zstd"] {
+            let dir = temp_dir(&format!("retention-race-{}", compression));
+            let log_file = dir.join("app.log");
+            let config = file_config(
+                &log_file,
+                &format!(
+                    r#"{{"max_log_file_size":200,"max_log_file_count":3,"rotation_compression":"{}"}}"#,
+                    compression
+                ),
+            );
+            unsafe {
+                let handle = create_logger(config.as_ptr());
+                assert!(handle > 0);
+                for i in 0..200 {
+                    let input = c(&format!(r#"{{"message":"record number {:04}"}}"#, i));
+                    assert_eq!(execute_with(handle, input.as_ptr()), error_code::OK);
+                }
+                assert_eq!(destroy_logger(handle), error_code::OK);
+
+                // Archives still compressing at the last rotation were kept;
+                // starting up again prunes them.
+                let handle = create_logger(config.as_ptr());
+                assert!(handle > 0);
+                assert_eq!(destroy_logger(handle), error_code::OK);
+            }
+
+            let names: Vec<String> = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            assert!(
+                names.iter().all(|name| !name.ends_with(".tmp")),
+                "{:?}",
+                names
+            );
+            // The active file plus two archives.
+            assert_eq!(names.len(), 3, "{:?}", names);
+        }
+    }
 
-        let log_file_path = LOG_FILE.lock().unwrap().clone();
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&log_file_path)
-            .expect("Failed to open log file");
+    #[test]
+    fn execute_sees_a_consistent_config_while_reinitializing() {
+        let _guard = lock_default_logger();
+        let dir = temp_dir("reinitialize");
+        let log_file = dir.join("app.log");
+        let configs = [
+            file_config(&log_file, r#"{"file_format":"A {level} {message}"}"#),
+            file_config(
+                &log_file,
+                r#"{"file_format":"B {message} {level} B","minimum_log_level":"debug"}"#,
+            ),
+        ];
+        const THREADS: usize = 8;
+        const RECORDS: usize = 500;
 
-        if *LOG_TO_FILE_COLORED.lock().unwrap() {
-            if let Err(e) = writeln!(file, "{}", log_message) {
-                eprintln!("Failed to write to log file: {}", e);
-                return -1;
+        unsafe {
+            assert_eq!(initialize(configs[0].as_ptr()), error_code::OK);
+        }
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let reinitialized = std::thread::scope(|scope| {
+            let writers: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    scope.spawn(move || {
+                        for i in 0..RECORDS {
+                            let input = c(&format!(r#"{{"message":"t{}-{}"}}"#, t, i));
+                            assert_eq!(unsafe { execute(input.as_ptr()) }, error_code::OK);
+                        }
+                    })
+                })
+                .collect();
+            let reinitializer = scope.spawn(|| {
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let config = &configs[count % 2];
+                    assert_eq!(unsafe { initialize(config.as_ptr()) }, error_code::OK);
+                    count += 1;
+                }
+                count
+            });
+            for writer in writers {
+                writer.join().unwrap();
             }
-        } else {
-            let log_message_plain = format!(
-                "[{}] [{}] {}: {}",
-                timestamp,
-                input_data.level.to_string(),
-                app_name,
-                input_data.message
-            );
-            if let Err(e) = writeln!(file, "{}", log_message_plain) {
-                eprintln!("Failed to write to log file: {}", e);
-                return -1;
+            done.store(true, Ordering::Relaxed);
+            reinitializer.join().unwrap()
+        });
+        assert_eq!(teardown(), error_code::OK);
+        assert!(reinitialized > 1);
+
+        let contents = std::fs::read_to_string(&log_file).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), THREADS * RECORDS);
+        for line in lines {
+            let parts: Vec<&str> = line.split(' ').collect();
+            let consistent = match parts.as_slice() {
+                ["A", "INFO", message] => message.starts_with('t'),
+                ["B", message, "INFO", "B"] => message.starts_with('t'),
+                _ => false,
+            };
+            assert!(consistent, "line mixes both configs: {:?}", line);
+        }
+    }
+
+    #[test]
+    fn level_names_aliases_and_numbers() {
+        let table = [
+            ("trace", "TRACE"),
+            ("DEBUG", "DEBUG"),
+            (" info ", "INFO"),
+            ("notice", "INFO"),
+            ("warn", "WARN"),
+            ("Warning", "WARN"),
+            ("error", "ERROR"),
+            ("err", "ERROR"),
+            ("fatal", "FATAL"),
+            ("critical", "FATAL"),
+            ("crit", "FATAL"),
+            ("alert", "FATAL"),
+            ("emerg", "FATAL"),
+            // Bare numbers and `syslog:N` are syslog severities.
+            ("0", "FATAL"),
+            ("1", "FATAL"),
+            ("2", "FATAL"),
+            ("3", "ERROR"),
+            ("4", "WARN"),
+            ("5", "INFO"),
+            ("6", "INFO"),
+            ("7", "DEBUG"),
+            ("syslog:4", "WARN"),
+            // `otel:N` are OpenTelemetry severity numbers.
+            ("otel:1", "TRACE"),
+            ("otel:4", "TRACE"),
+            ("otel:5", "DEBUG"),
+            ("otel:9", "INFO"),
+            ("otel:12", "INFO"),
+            ("OTEL:13", "WARN"),
+            ("otel:17", "ERROR"),
+            ("otel:21", "FATAL"),
+            ("otel:24", "FATAL"),
+        ];
+        for (input, expected) in table {
+            let level = input.parse::<LogLevel>();
+            assert_eq!(
+                level.map(|l| l.to_string()),
+                Ok(expected.to_string()),
+                "{:?}",
+                input
+            );
+        }
+        for input in [
+            "", "loud", "8", "17", "-1", "syslog:8", "otel:0", "otel:25", "otel:", "otel:x",
+        ] {
+            assert!(input.parse::<LogLevel>().is_err(), "{:?}", input);
+        }
+
+        let level: LogLevel = serde_json::from_str("3").unwrap();
+        assert_eq!(level, LogLevel::Error);
+        let level: LogLevel = serde_json::from_str("\"otel:17\"").unwrap();
+        assert_eq!(level, LogLevel::Error);
+        assert!(serde_json::from_str::<LogLevel>("17").is_err());
+    }
+
+    #[test]
+    fn levels_map_to_syslog_and_otel_severities() {
+        let table = [
+            (LogLevel::Trace, 7, 1),
+            (LogLevel::Debug, 7, 5),
+            (LogLevel::Info, 6, 9),
+            (LogLevel::Warn, 4, 13),
+            (LogLevel::Error, 3, 17),
+            (LogLevel::Fatal, 2, 21),
+        ];
+        for (level, syslog, otel) in table {
+            assert_eq!(level.syslog_severity(), syslog, "{}", level);
+            assert_eq!(level.otel_severity(), otel, "{}", level);
+            assert_eq!(LogLevel::from_otel_severity(otel), Some(level));
+        }
+    }
+
+    #[test]
+    fn execute_accepts_otel_levels() {
+        let dir = temp_dir("otel-levels");
+        let log_file = dir.join("app.log");
+        unsafe {
+            let handle = create_logger(
+                file_config(&log_file, r#"{"minimum_log_level":"otel:13"}"#).as_ptr(),
+            );
+            assert!(handle > 0);
+            for input in [
+                r#"{"message":"a","level":"otel:17"}"#,
+                r#"{"message":"b","level":"otel:9"}"#,
+                r#"{"message":"c","level":1}"#,
+                r#"{"message":"d","level":"syslog:4"}"#,
+            ] {
+                assert_eq!(execute_with(handle, c(input).as_ptr()), error_code::OK);
             }
+            assert_error(
+                execute_with(handle, c(r#"{"message":"e","level":17}"#).as_ptr()) as i64,
+                error_code::UNKNOWN_LEVEL,
+                "unknown log level: \"17\"",
+            );
+            assert_eq!(destroy_logger(handle), error_code::OK);
         }
+        assert_eq!(
+            std::fs::read_to_string(&log_file).unwrap(),
+            "ERROR a\nFATAL c\nWARN d\n"
+        );
     }
 
-    0
-}
+    #[test]
+    fn custom_levels_compare_by_severity_only() {
+        for name in ["AUDIT", "SUCCESS"] {
+            assert!(custom(name, 9).is_at_least(&LogLevel::Info), "{}", name);
+            assert!(LogLevel::Info.is_at_least(&custom(name, 9)), "{}", name);
+            assert!(!custom(name, 8).is_at_least(&LogLevel::Info), "{}", name);
+            assert!(custom(name, 13).is_at_least(&LogLevel::Warn), "{}", name);
+        }
+        assert_ne!(custom("AUDIT", 9), LogLevel::Info);
+        assert_eq!(custom("AUDIT", 9), custom("AUDIT", 9));
+    }
 
-#[no_mangle]
-pub extern "C" fn teardown() -> i32 {
-    APP_NAME.lock().unwrap().clear();
-    LOG_FILE.lock().unwrap().clear();
-    *LOG_TO_FILE.lock().unwrap() = false;
-    *LOG_TO_CONSOLE.lock().unwrap() = false;
-    0
+    #[test]
+    fn custom_levels_at_the_threshold_are_written() {
+        let dir = temp_dir("custom-threshold");
+        let log_file = dir.join("app.log");
+        let config = file_config(
+            &log_file,
+            r#"{"custom_levels":[
+                {"name":"AUDIT","severity":9},
+                {"name":"SUCCESS","severity":9},
+                {"name":"CHATTER","severity":8},
+                {"name":"ALARM","severity":13}
+            ],"level_filters":{"test -> db":"warn"}}"#,
+        );
+        unsafe {
+            let handle = create_logger(config.as_ptr());
+            assert!(handle > 0);
+            for input in [
+                r#"{"message":"a","level":"audit"}"#,
+                r#"{"message":"b","level":"SUCCESS"}"#,
+                r#"{"message":"c","level":"chatter"}"#,
+                r#"{"message":"d","level":"alarm","sub_app_name":"db"}"#,
+                r#"{"message":"e","level":"audit","sub_app_name":"db"}"#,
+            ] {
+                assert_eq!(execute_with(handle, c(input).as_ptr()), error_code::OK);
+            }
+            assert_eq!(destroy_logger(handle), error_code::OK);
+        }
+        assert_eq!(
+            std::fs::read_to_string(&log_file).unwrap(),
+            "AUDIT a\nSUCCESS b\nALARM d\n"
+        );
+    }
 }
@@ -0,0 +1,705 @@
+use arc_swap::ArcSwapOption;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::config::{LogFormat, Settings};
+use crate::file::{FlushPolicy, IntervalFlusher, LogFile};
+use crate::journald::{JournaldConfig, JournaldSink};
+use crate::queue::{BoundedQueue, OverflowPolicy};
+use crate::rotation::{self, Archive, Compression, Period};
+use crate::sink::{Sink, SinkKind};
+use crate::syslog::{SyslogConfig, SyslogSink};
+use crate::template::{self, Record};
+use crate::watch::ConfigWatcher;
+use crate::{ffi_guard, ExecutionInput, LogLevel, PluginError};
+
+/// A record waiting for the writer thread, stamped with the time `execute` was called.
+type QueuedRecord = (ExecutionInput, DateTime<Utc>);
+
+/// The background thread that performs output in `async_mode`.
+struct AsyncWriter {
+    queue: Arc<BoundedQueue<QueuedRecord>>,
+    thread: JoinHandle<()>,
+}
+
+/// A file sink's open file and the rotation period its contents belong to.
+#[derive(Default)]
+struct FileState {
+    file: Option<LogFile>,
+    /// Worked out from the file's mtime on first use.
+    period: Option<Period>,
+}
+
+/// What retention did to the archives of one log file, reported once the
+/// files lock is released.
+#[derive(Default)]
+struct Pruned {
+    removed: Vec<Archive>,
+    /// Why pruning failed, if it did.
+    error: Option<String>,
+}
+
+impl Pruned {
+    fn from_result(log_file: &str, result: std::io::Result<Vec<Archive>>) -> Self {
+        match result {
+            Ok(removed) => Pruned {
+                removed,
+                error: None,
+            },
+            Err(e) => Pruned {
+                removed: Vec::new(),
+                error: Some(format!("failed to prune archives of {}: {}", log_file, e)),
+            },
+        }
+    }
+}
+
+/// One independent logger: its configuration snapshot plus the state that
+/// outlives a single call (open files, connections, writer threads).
+#[derive(Default)]
+pub struct Logger {
+    settings: ArcSwapOption<Settings>,
+    /// Keyed by path, so file sinks sharing a path share the file.
+    files: Mutex<HashMap<String, FileState>>,
+    /// Connections, reused by every sink with the same config.
+    syslog: Mutex<Vec<SyslogSink>>,
+    journald: Mutex<Vec<JournaldSink>>,
+    async_writer: Mutex<Option<AsyncWriter>>,
+    compression_threads: Mutex<Vec<JoinHandle<()>>>,
+    /// Archives whose compression is still running, which retention leaves alone.
+    compressing: Arc<Mutex<HashSet<PathBuf>>>,
+    dropped: AtomicU64,
+    /// Serializes `initialize` and `reconfigure`.
+    config_lock: Mutex<()>,
+    watcher: Mutex<Option<ConfigWatcher>>,
+    /// Running while the flush policy is `interval_ms`.
+    flusher: Mutex<Option<IntervalFlusher>>,
+}
+
+/// The period the log file on disk belongs to. The file may predate this run,
+/// so its period starts from its mtime.
+fn initial_period(settings: &Settings, log_file: &str) -> Option<Period> {
+    settings.rotation_interval.as_ref().map(|interval| {
+        let started = std::fs::metadata(log_file)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        interval.period(settings.clock.localize(started))
+    })
+}
+
+/// The `app -> sub_app` target a record is logged under.
+fn target(settings: &Settings, input: &ExecutionInput) -> String {
+    let app_name = input.app_name.as_ref().unwrap_or(&settings.app_name);
+    match &input.sub_app_name {
+        Some(sub_app_name) => format!("{} -> {}", app_name, sub_app_name),
+        None => app_name.clone(),
+    }
+}
+
+/// Renders `record` as one line of a console or file sink.
+fn format_line(
+    settings: &Settings,
+    sink: &Sink,
+    record: &Record,
+    now: DateTime<Utc>,
+    colored: bool,
+) -> String {
+    match sink.format {
+        LogFormat::Json => serde_json::json!({
+            "timestamp": settings.clock.rfc3339(now),
+            "level": record.level.to_string(),
+            "severity_number": record.level.otel_severity(),
+            "app": record.app,
+            "sub_app": record.sub_app,
+            "message": record.message,
+            "fields": record.fields,
+        })
+        .to_string(),
+        LogFormat::Text => sink.template.render(record, colored),
+    }
+}
+
+impl Logger {
+    pub fn initialize(self: &Arc<Self>, settings: Settings) -> Result<(), PluginError> {
+        let _config_guard = self.config_lock.lock().unwrap();
+        let settings = Arc::new(settings);
+
+        // Records queued under the previous configuration are written with it.
+        self.stop_async_writer()?;
+        self.dropped.store(0, Ordering::Relaxed);
+        self.close_log_files(&[])?;
+        self.syslog.lock().unwrap().clear();
+        self.journald.lock().unwrap().clear();
+
+        self.settings.store(Some(Arc::clone(&settings)));
+        self.sync_watcher(&settings);
+        self.sync_flusher(&settings);
+
+        if settings.async_mode {
+            self.start_async_writer(settings.async_queue_capacity, settings.async_overflow);
+        }
+
+        if !settings.env_overrides.is_empty() {
+            let fields = settings
+                .env_overrides
+                .iter()
+                .map(|(var, value)| (var.to_string(), value.clone().into()))
+                .collect();
+            self.log_internal(
+                LogLevel::Debug,
+                "configuration overridden from the environment".to_string(),
+                fields,
+            )?;
+        }
+        if !settings.ignored_env_overrides.is_empty() {
+            self.log_internal(
+                LogLevel::Warn,
+                format!(
+                    "ignoring {} because sinks is set",
+                    settings.ignored_env_overrides.join(", ")
+                ),
+                serde_json::Map::new(),
+            )?;
+        }
+
+        if settings.enable_log_rotation {
+            for log_file in settings.log_files() {
+                let result = self.apply_retention(settings.as_ref(), Path::new(log_file), 0, 0);
+                self.report_pruned(&[Pruned::from_result(log_file, result)])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the running configuration with the one `build` derives from
+    /// it. Only the state affected by the change is touched: only files and
+    /// connections no sink uses anymore are closed, and the writer thread is
+    /// restarted only if the async settings changed.
+    pub fn reconfigure(
+        self: &Arc<Self>,
+        build: impl FnOnce(&Settings) -> Result<Settings, PluginError>,
+    ) -> Result<(), PluginError> {
+        let _config_guard = self.config_lock.lock().unwrap();
+        let Some(current) = self.settings.load_full() else {
+            return Err(PluginError::NotInitialized);
+        };
+        let settings = Arc::new(build(&current)?);
+
+        let async_changed = settings.async_mode != current.async_mode
+            || settings.async_queue_capacity != current.async_queue_capacity
+            || settings.async_overflow != current.async_overflow;
+        let period_changed = settings.rotation_interval != current.rotation_interval
+            || settings.clock.timezone != current.clock.timezone;
+
+        if async_changed {
+            self.stop_async_writer()?;
+        }
+        self.close_log_files(&settings.log_files().collect::<Vec<_>>())?;
+        for (path, state) in self.files.lock().unwrap().iter_mut() {
+            if settings.flush_policy != current.flush_policy {
+                if let Some(file) = state.file.as_mut() {
+                    file.set_policy(settings.flush_policy)
+                        .map_err(|e| PluginError::FileWrite(path.clone(), e))?;
+                }
+            }
+            if period_changed {
+                state.period = None;
+            }
+        }
+
+        self.syslog.lock().unwrap().retain(|open| {
+            settings.sinks.iter().any(
+                |sink| matches!(&sink.kind, SinkKind::Syslog(config) if config == open.config()),
+            )
+        });
+        self.journald.lock().unwrap().retain(|open| {
+            settings.sinks.iter().any(
+                |sink| matches!(&sink.kind, SinkKind::Journald(config) if config == open.config()),
+            )
+        });
+        self.settings.store(Some(Arc::clone(&settings)));
+        self.sync_watcher(&settings);
+        self.sync_flusher(&settings);
+
+        if async_changed && settings.async_mode {
+            self.start_async_writer(settings.async_queue_capacity, settings.async_overflow);
+        }
+        Ok(())
+    }
+
+    /// Starts, replaces or stops the `config_file` watcher to match `settings`.
+    /// Replaced watchers are not joined; their threads exit on their next poll.
+    fn sync_watcher(self: &Arc<Self>, settings: &Settings) {
+        let mut watcher = self.watcher.lock().unwrap();
+        let wanted = settings.config_file.as_deref().map(Path::new);
+        if watcher.as_ref().map(|w| w.path()) == wanted {
+            return;
+        }
+
+        *watcher = wanted.map(|path| {
+            let logger = Arc::downgrade(self);
+            ConfigWatcher::spawn(
+                path.to_path_buf(),
+                settings.config_poll_interval,
+                move || {
+                    let Some(logger) = logger.upgrade() else {
+                        return false;
+                    };
+                    let reloaded =
+                        logger.reconfigure(|current| Settings::from_value(current.source.clone()));
+                    if let Err(e) = reloaded {
+                        eprintln!("logger: keeping previous configuration: {}", e);
+                    }
+                    true
+                },
+            )
+        });
+    }
+
+    /// Starts, replaces or stops the interval flusher to match the flush policy.
+    fn sync_flusher(self: &Arc<Self>, settings: &Settings) {
+        let mut flusher = self.flusher.lock().unwrap();
+        // A zero interval already flushes on every write.
+        let wanted = match settings.flush_policy {
+            FlushPolicy::Interval(interval) if !interval.is_zero() => Some(interval),
+            _ => None,
+        };
+        if flusher.as_ref().map(|f| f.interval()) == wanted {
+            return;
+        }
+
+        *flusher = wanted.map(|interval| {
+            let logger = Arc::downgrade(self);
+            IntervalFlusher::spawn(interval, move || {
+                let Some(logger) = logger.upgrade() else {
+                    return false;
+                };
+                logger.flush_buffered();
+                true
+            })
+        });
+    }
+
+    /// Writes out lines buffered in the open log files.
+    fn flush_buffered(&self) {
+        for (path, state) in self.files.lock().unwrap().iter_mut() {
+            if let Some(file) = state.file.as_mut() {
+                if let Err(e) = file.flush_buffered() {
+                    eprintln!("logger: failed to flush log file {}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    /// Flushes all output, stops background threads and forgets the
+    /// configuration.
+    pub fn teardown(&self) -> Result<(), PluginError> {
+        // A reload finishing while the watcher is joined may start a new one.
+        loop {
+            let Some(watcher) = self.watcher.lock().unwrap().take() else {
+                break;
+            };
+            watcher.stop();
+        }
+        let flusher = self.flusher.lock().unwrap().take();
+        if let Some(flusher) = flusher {
+            flusher.stop();
+        }
+        let stopped = self.stop_async_writer();
+        let closed = self.close_log_files(&[]);
+        self.syslog.lock().unwrap().clear();
+        self.journald.lock().unwrap().clear();
+        self.settings.store(None);
+        for handle in self.compression_threads.lock().unwrap().drain(..) {
+            let _ = handle.join();
+        }
+        stopped.and(closed)
+    }
+
+    pub fn execute(&self, input_data: ExecutionInput) -> Result<(), PluginError> {
+        let Some(settings) = self.settings.load_full() else {
+            return Err(PluginError::NotInitialized);
+        };
+        let now = Utc::now();
+
+        if !settings.enabled(&input_data.level, &target(&settings, &input_data)) {
+            return Ok(());
+        }
+
+        let queue = self
+            .async_writer
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|writer| Arc::clone(&writer.queue));
+        match queue {
+            // The writer may be closing for a reconfigure or teardown; write
+            // the record here rather than lose it.
+            Some(queue) => match queue.push((input_data, now)) {
+                Ok(()) => Ok(()),
+                Err((input_data, now)) => self.log_input(input_data, now),
+            },
+            None => self.log_input(input_data, now),
+        }
+    }
+
+    /// Looks up a level declared in `custom_levels`, ignoring case.
+    pub fn custom_level(&self, name: &str) -> Option<LogLevel> {
+        let settings = self.settings.load();
+        settings
+            .as_ref()?
+            .custom_levels
+            .iter()
+            .find(|level| level.to_string().eq_ignore_ascii_case(name.trim()))
+            .cloned()
+    }
+
+    /// Returns how many records were discarded by the async overflow policy
+    /// since the last `initialize`.
+    pub fn dropped_records(&self) -> u64 {
+        let current = self
+            .async_writer
+            .lock()
+            .map(|writer| writer.as_ref().map_or(0, |w| w.queue.dropped()))
+            .unwrap_or(0);
+        self.dropped.load(Ordering::Relaxed) + current
+    }
+
+    fn start_async_writer(self: &Arc<Self>, capacity: usize, overflow: OverflowPolicy) {
+        let queue = Arc::new(BoundedQueue::new(capacity, overflow));
+        let worker_queue = Arc::clone(&queue);
+        let logger = Arc::clone(self);
+        let thread = std::thread::spawn(move || {
+            while let Some((input, time)) = worker_queue.pop() {
+                ffi_guard(|| logger.log_input(input, time));
+            }
+        });
+        *self.async_writer.lock().unwrap() = Some(AsyncWriter { queue, thread });
+    }
+
+    /// Drains the queue, joins the writer thread and reports dropped records.
+    fn stop_async_writer(&self) -> Result<(), PluginError> {
+        let Some(writer) = self.async_writer.lock().unwrap().take() else {
+            return Ok(());
+        };
+        writer.queue.close();
+        let _ = writer.thread.join();
+
+        let dropped = writer.queue.dropped();
+        self.dropped.fetch_add(dropped, Ordering::Relaxed);
+        if dropped > 0 {
+            self.log_internal(
+                LogLevel::Warn,
+                format!(
+                    "dropped {} records because the async queue was full",
+                    dropped
+                ),
+                serde_json::Map::new(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Flushes and closes the open log files, except those in `keep`.
+    fn close_log_files(&self, keep: &[&str]) -> Result<(), PluginError> {
+        let mut files = self.files.lock().unwrap();
+        let closed: Vec<String> = files
+            .keys()
+            .filter(|path| !keep.contains(&path.as_str()))
+            .cloned()
+            .collect();
+        let mut result = Ok(());
+        for path in closed {
+            if let Some(mut file) = files.remove(&path).and_then(|state| state.file) {
+                if let Err(e) = file.flush() {
+                    result = result.and(Err(PluginError::FileWrite(path, e)));
+                }
+            }
+        }
+        result
+    }
+
+    /// Applies the retention limits to the archives of `log_file`, skipping
+    /// those still being compressed.
+    fn apply_retention(
+        &self,
+        settings: &Settings,
+        log_file: &Path,
+        incoming: usize,
+        incoming_size: u64,
+    ) -> std::io::Result<Vec<Archive>> {
+        let compressing = self.compressing.lock().unwrap();
+        settings
+            .retention
+            .apply(log_file, incoming, incoming_size, |archive| {
+                compressing.contains(archive)
+            })
+    }
+
+    /// Rotates `log_file_path` if it is due, closing its file first when it is
+    /// open. Returns what retention did along the way; failing to prune does
+    /// not stop the rotation.
+    fn rotate_log_file(
+        &self,
+        settings: &Settings,
+        log_file_path: &str,
+        state: &mut FileState,
+    ) -> Result<Pruned, PluginError> {
+        if !settings.enable_log_rotation {
+            return Ok(Pruned::default());
+        }
+
+        let max_count = settings.max_log_file_count;
+        let clock = &settings.clock;
+        let now = clock.localize(Utc::now());
+
+        // Label of the period the current file covers, if that period is over.
+        let mut expired_label = None;
+        let mut current_label = None;
+        if let Some(interval) = &settings.rotation_interval {
+            let current = match &mut state.period {
+                Some(period) => period,
+                None => state.period.insert(
+                    initial_period(settings, log_file_path).unwrap_or_else(|| interval.period(now)),
+                ),
+            };
+            if current.end.is_some_and(|end| now >= end) {
+                expired_label = Some(interval.label(current.start));
+                *current = interval.period(now);
+            }
+            current_label = Some(interval.label(current.start));
+        }
+
+        let log_size = match &state.file {
+            Some(file) => Some(file.size()),
+            None => std::fs::metadata(log_file_path).ok().map(|m| m.len()),
+        };
+        let mut pruned = Pruned::default();
+
+        if let Some(log_size) = log_size {
+            let size_exceeded = log_size > settings.max_log_file_size;
+            let period_expired = expired_label.is_some() && log_size > 0;
+            if size_exceeded || period_expired {
+                let log_path = Path::new(log_file_path);
+                let rotation_error = |e| PluginError::Rotation(log_file_path.to_string(), e);
+
+                if let Some(mut file) = state.file.take() {
+                    file.flush()
+                        .map_err(|e| PluginError::FileWrite(log_file_path.to_string(), e))?;
+                }
+
+                // With room for the active file only, there is nothing to archive.
+                if max_count == 1 {
+                    std::fs::remove_file(log_path).map_err(rotation_error)?;
+                    return Ok(pruned);
+                }
+                let result = self.apply_retention(settings, log_path, 1, log_size);
+                pruned = Pruned::from_result(log_file_path, result);
+
+                let label = expired_label
+                    .or(current_label)
+                    .unwrap_or_else(|| clock.archive_stamp(Utc::now()));
+                let compression = settings.rotation_compression;
+                let taken = |path: &Path| {
+                    let mut compressed = path.as_os_str().to_owned();
+                    compressed.push(compression.extension());
+                    path.exists() || Path::new(&compressed).exists()
+                };
+                let mut archive_path = rotation::archive_path(log_path, &label, None);
+                let mut suffix = 1;
+                while taken(&archive_path) {
+                    archive_path = rotation::archive_path(log_path, &label, Some(suffix));
+                    suffix += 1;
+                }
+                std::fs::rename(log_path, &archive_path).map_err(rotation_error)?;
+
+                if compression != Compression::None {
+                    // Compress off the logging thread; teardown waits for it to finish.
+                    let compressing = Arc::clone(&self.compressing);
+                    compressing.lock().unwrap().insert(archive_path.clone());
+                    let handle = std::thread::spawn(move || {
+                        if let Err(e) = compression.compress(&archive_path) {
+                            eprintln!(
+                                "logger: failed to compress {}: {}",
+                                archive_path.display(),
+                                e
+                            );
+                        }
+                        compressing.lock().unwrap().remove(&archive_path);
+                    });
+                    let mut threads = self.compression_threads.lock().unwrap();
+                    threads.retain(|t| !t.is_finished());
+                    threads.push(handle);
+                }
+            }
+        }
+
+        Ok(pruned)
+    }
+
+    /// Logs a record produced by the plugin itself.
+    fn log_internal(
+        &self,
+        level: LogLevel,
+        message: String,
+        fields: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<(), PluginError> {
+        self.log_input(
+            ExecutionInput {
+                message,
+                level,
+                app_name: None,
+                sub_app_name: Some("logger".to_string()),
+                fields,
+            },
+            Utc::now(),
+        )
+    }
+
+    /// Reports archives deleted by retention at debug level, and failures to
+    /// prune as warnings.
+    fn report_pruned(&self, pruned: &[Pruned]) -> Result<(), PluginError> {
+        for pruned in pruned {
+            for archive in &pruned.removed {
+                let mut fields = serde_json::Map::new();
+                fields.insert("size".to_string(), archive.size.into());
+                self.log_internal(
+                    LogLevel::Debug,
+                    format!("deleted archive {}", archive.path.display()),
+                    fields,
+                )?;
+            }
+            if let Some(error) = &pruned.error {
+                self.log_internal(LogLevel::Warn, error.clone(), serde_json::Map::new())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn log_input(&self, input_data: ExecutionInput, now: DateTime<Utc>) -> Result<(), PluginError> {
+        let Some(settings) = self.settings.load_full() else {
+            return Err(PluginError::NotInitialized);
+        };
+
+        let base_app_name = input_data
+            .app_name
+            .as_ref()
+            .unwrap_or(&settings.app_name)
+            .clone();
+        let app_name = target(&settings, &input_data);
+        if !settings.enabled(&input_data.level, &app_name) {
+            return Ok(());
+        }
+        let timestamp = settings.clock.format(now);
+
+        let record = Record {
+            timestamp: &timestamp,
+            level: &input_data.level,
+            app: &base_app_name,
+            sub_app: input_data.sub_app_name.as_deref(),
+            target: &app_name,
+            message: &input_data.message,
+            fields: &input_data.fields,
+            seq: template::next_seq(),
+        };
+
+        // A failing sink does not keep the record from the others; the first
+        // error is returned.
+        let mut result = Ok(());
+        let mut pruned = Vec::new();
+        for sink in &settings.sinks {
+            if !sink.enabled(&input_data.level, &app_name, &settings) {
+                continue;
+            }
+            let written = match &sink.kind {
+                SinkKind::Console { stream } => {
+                    let colored = sink.color.enabled(stream.is_terminal(&input_data.level));
+                    let line = format_line(&settings, sink, &record, now, colored);
+                    // A closed console is not worth failing the record for.
+                    let _ = stream.write_line(&input_data.level, &line);
+                    Ok(())
+                }
+                SinkKind::File { path } => {
+                    let colored = sink.color.enabled(false);
+                    let line = format_line(&settings, sink, &record, now, colored);
+                    self.write_log_file(&settings, path, &line)
+                        .map(|file_pruned| pruned.push(file_pruned))
+                }
+                SinkKind::Syslog(config) => self.send_syslog(&settings, config, &record, now),
+                SinkKind::Journald(config) => self.send_journald(config, &record),
+            };
+            result = result.and(written);
+        }
+
+        let reported = self.report_pruned(&pruned);
+        result.and(reported)
+    }
+
+    /// Appends `line` to `log_file_path`, rotating first if it is due.
+    fn write_log_file(
+        &self,
+        settings: &Settings,
+        log_file_path: &str,
+        line: &str,
+    ) -> Result<Pruned, PluginError> {
+        let mut files = self.files.lock().unwrap();
+        let state = files.entry(log_file_path.to_string()).or_default();
+        let pruned = self.rotate_log_file(settings, log_file_path, state)?;
+
+        let file = match state.file.take() {
+            Some(file) => file,
+            None => LogFile::open(Path::new(log_file_path), settings.flush_policy)
+                .map_err(|e| PluginError::FileOpen(log_file_path.to_string(), e))?,
+        };
+        state
+            .file
+            .insert(file)
+            .write_line(line)
+            .map_err(|e| PluginError::FileWrite(log_file_path.to_string(), e))?;
+        Ok(pruned)
+    }
+
+    fn send_syslog(
+        &self,
+        settings: &Settings,
+        config: &SyslogConfig,
+        record: &Record,
+        now: DateTime<Utc>,
+    ) -> Result<(), PluginError> {
+        let mut open = self.syslog.lock().unwrap();
+        let index = match open.iter().position(|sink| sink.config() == config) {
+            Some(index) => index,
+            None => {
+                let sink = SyslogSink::connect(config)
+                    .map_err(|e| PluginError::SinkWrite(config.describe(), e))?;
+                open.push(sink);
+                open.len() - 1
+            }
+        };
+        open[index]
+            .send(record, settings.clock.localize(now))
+            .map_err(|e| PluginError::SinkWrite(config.describe(), e))
+    }
+
+    fn send_journald(&self, config: &JournaldConfig, record: &Record) -> Result<(), PluginError> {
+        let mut open = self.journald.lock().unwrap();
+        let index = match open.iter().position(|sink| sink.config() == config) {
+            Some(index) => index,
+            None => {
+                let sink = JournaldSink::connect(config)
+                    .map_err(|e| PluginError::SinkWrite(config.describe(), e))?;
+                open.push(sink);
+                open.len() - 1
+            }
+        };
+        open[index]
+            .send(record)
+            .map_err(|e| PluginError::SinkWrite(config.describe(), e))
+    }
+}
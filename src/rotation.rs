@@ -0,0 +1,894 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, NaiveTime,
+    TimeZone, Timelike,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Upper bound for cron searches. A spec that can fire at all does so within
+/// eight years: Feb 29 may be eight years apart across a century.
+const CRON_SEARCH_DAYS: i64 = 8 * 366;
+
+/// The longest each month gets, for telling whether a day of month can occur.
+const MONTH_DAYS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// A single cron field, stored as the set of allowed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CronField {
+    allowed: Vec<bool>,
+    wildcard: bool,
+}
+
+impl CronField {
+    fn parse(spec: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut allowed = vec![false; max as usize + 1];
+        for part in spec.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (
+                    range,
+                    step.parse::<u32>()
+                        .ok()
+                        .filter(|s| *s > 0)
+                        .ok_or_else(|| format!("invalid step in {:?}", part))?,
+                ),
+                None => (part, 1),
+            };
+            let (start, end) = if range == "*" {
+                (min, max)
+            } else if let Some((a, b)) = range.split_once('-') {
+                (
+                    parse_cron_value(a, min, max)?,
+                    parse_cron_value(b, min, max)?,
+                )
+            } else {
+                let value = parse_cron_value(range, min, max)?;
+                (value, if part.contains('/') { max } else { value })
+            };
+            if start > end {
+                return Err(format!("invalid range {:?}", part));
+            }
+            for value in (start..=end).step_by(step as usize) {
+                allowed[value as usize] = true;
+            }
+        }
+        Ok(CronField {
+            allowed,
+            wildcard: spec == "*",
+        })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.allowed.get(value as usize).copied().unwrap_or(false)
+    }
+}
+
+fn parse_cron_value(s: &str, min: u32, max: u32) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("value {:?} out of range {}-{}", s, min, max)),
+    }
+}
+
+/// A five field cron spec: `minute hour day-of-month month day-of-week`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSpec {
+    source: String,
+    minute: CronField,
+    hour: CronField,
+    day_of_month: CronField,
+    month: CronField,
+    day_of_week: CronField,
+}
+
+impl CronSpec {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        if parts.len() != 5 {
+            return Err(format!(
+                "invalid rotation interval {:?}: expected hourly, daily, weekly or a 5 field cron spec",
+                spec
+            ));
+        }
+        let wrap = |e: String| format!("invalid cron spec {:?}: {}", spec, e);
+        let mut day_of_week = CronField::parse(parts[4], 0, 7).map_err(wrap)?;
+        // Both 0 and 7 mean Sunday.
+        if day_of_week.allowed[7] {
+            day_of_week.allowed[0] = true;
+        }
+        let spec = CronSpec {
+            source: spec.to_string(),
+            minute: CronField::parse(parts[0], 0, 59).map_err(wrap)?,
+            hour: CronField::parse(parts[1], 0, 23).map_err(wrap)?,
+            day_of_month: CronField::parse(parts[2], 1, 31).map_err(wrap)?,
+            month: CronField::parse(parts[3], 1, 12).map_err(wrap)?,
+            day_of_week,
+        };
+        if !spec.can_fire() {
+            return Err(format!("invalid cron spec {:?}: never fires", spec.source));
+        }
+        Ok(spec)
+    }
+
+    /// Whether some date matches. Every weekday occurs in every month, so
+    /// only a day of month restricted on its own can rule out all dates.
+    fn can_fire(&self) -> bool {
+        if self.day_of_month.wildcard || !self.day_of_week.wildcard {
+            return true;
+        }
+        (1..=12).any(|month| {
+            self.month.matches(month)
+                && (1..=MONTH_DAYS[month as usize - 1]).any(|day| self.day_of_month.matches(day))
+        })
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let dom = self.day_of_month.matches(date.day());
+        let dow = self
+            .day_of_week
+            .matches(date.weekday().num_days_from_sunday());
+        // Like cron, restricting both day fields means either may match.
+        let day = match (self.day_of_month.wildcard, self.day_of_week.wildcard) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        };
+        day && self.month.matches(date.month())
+    }
+
+    /// The first time the spec fires after the minute containing `t`. Skips
+    /// whole months, days and hours that cannot match.
+    fn next_after(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = t + Duration::days(CRON_SEARCH_DAYS);
+        let mut candidate = t.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        while candidate <= limit {
+            let date = candidate.date();
+            candidate = if !self.month.matches(date.month()) {
+                date.with_day(1)?
+                    .checked_add_months(Months::new(1))?
+                    .and_time(NaiveTime::MIN)
+            } else if !self.day_matches(date) {
+                date.succ_opt()?.and_time(NaiveTime::MIN)
+            } else if !self.hour.matches(candidate.hour()) {
+                candidate.with_minute(0)? + Duration::hours(1)
+            } else if !self.minute.matches(candidate.minute()) {
+                candidate + Duration::minutes(1)
+            } else {
+                return Some(candidate);
+            };
+        }
+        None
+    }
+
+    /// The last time the spec fired at or before the minute containing `t`.
+    fn last_at_or_before(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let limit = t - Duration::days(CRON_SEARCH_DAYS);
+        let mut candidate = t.with_second(0)?.with_nanosecond(0)?;
+        let one_minute = Duration::minutes(1);
+        while candidate >= limit {
+            let date = candidate.date();
+            candidate = if !self.month.matches(date.month()) {
+                date.with_day(1)?.and_time(NaiveTime::MIN) - one_minute
+            } else if !self.day_matches(date) {
+                date.and_time(NaiveTime::MIN) - one_minute
+            } else if !self.hour.matches(candidate.hour()) {
+                candidate.with_minute(0)? - one_minute
+            } else if !self.minute.matches(candidate.minute()) {
+                candidate - one_minute
+            } else {
+                return Some(candidate);
+            };
+        }
+        None
+    }
+}
+
+/// When the log file is rotated regardless of its size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RotationInterval {
+    Hourly,
+    Daily,
+    Weekly,
+    Cron(CronSpec),
+}
+
+/// The span of time the current log file covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: DateTime<FixedOffset>,
+    pub end: Option<DateTime<FixedOffset>>,
+}
+
+fn truncate_to_minute(t: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    t.with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(t)
+}
+
+fn start_of_day(t: DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+    t.with_time(NaiveTime::MIN).single().unwrap_or(t)
+}
+
+impl RotationInterval {
+    /// Returns the period containing `t`.
+    pub fn period(&self, t: DateTime<FixedOffset>) -> Period {
+        match self {
+            RotationInterval::Hourly => {
+                let start = truncate_to_minute(t).with_minute(0).unwrap_or(t);
+                Period {
+                    start,
+                    end: Some(start + Duration::hours(1)),
+                }
+            }
+            RotationInterval::Daily => {
+                let start = start_of_day(t);
+                Period {
+                    start,
+                    end: Some(start + Duration::days(1)),
+                }
+            }
+            RotationInterval::Weekly => {
+                let start =
+                    start_of_day(t) - Duration::days(t.weekday().num_days_from_monday() as i64);
+                Period {
+                    start,
+                    end: Some(start + Duration::weeks(1)),
+                }
+            }
+            RotationInterval::Cron(spec) => {
+                // Fixed offsets have no gaps, so local times map back one to one.
+                let offset = *t.offset();
+                let localize = |local| offset.from_local_datetime(&local).single();
+                let local = t.naive_local();
+                let start = spec
+                    .last_at_or_before(local)
+                    .and_then(localize)
+                    .unwrap_or_else(|| truncate_to_minute(t));
+                let end = spec.next_after(local).and_then(localize);
+                Period { start, end }
+            }
+        }
+    }
+
+    /// Filename-safe label describing the period starting at `start`.
+    pub fn label(&self, start: DateTime<FixedOffset>) -> String {
+        match self {
+            RotationInterval::Hourly => start.format("%Y-%m-%d_%H").to_string(),
+            RotationInterval::Daily => start.format("%Y-%m-%d").to_string(),
+            RotationInterval::Weekly => start.format("%G-W%V").to_string(),
+            RotationInterval::Cron(_) => start.format("%Y-%m-%d_%H-%M").to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for RotationInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RotationInterval::Hourly => write!(f, "hourly"),
+            RotationInterval::Daily => write!(f, "daily"),
+            RotationInterval::Weekly => write!(f, "weekly"),
+            RotationInterval::Cron(spec) => write!(f, "{}", spec.source),
+        }
+    }
+}
+
+impl FromStr for RotationInterval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hourly" => Ok(RotationInterval::Hourly),
+            "daily" => Ok(RotationInterval::Daily),
+            "weekly" => Ok(RotationInterval::Weekly),
+            _ => CronSpec::parse(s).map(RotationInterval::Cron),
+        }
+    }
+}
+
+impl Serialize for RotationInterval {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RotationInterval {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<RotationInterval>()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// How rotated archives are compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Extension appended to compressed archives, including the leading dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Compresses `path` next to itself and removes the original. Returns the
+    /// path of the compressed archive.
+    pub fn compress(&self, path: &Path) -> io::Result<PathBuf> {
+        if *self == Compression::None {
+            return Ok(path.to_path_buf());
+        }
+
+        let mut target = path.as_os_str().to_owned();
+        target.push(self.extension());
+        let target = PathBuf::from(target);
+        let mut partial = target.as_os_str().to_owned();
+        partial.push(".tmp");
+        let partial = PathBuf::from(partial);
+
+        let result = (|| {
+            let mut input = BufReader::new(File::open(path)?);
+            let output = BufWriter::new(File::create(&partial)?);
+            match self {
+                Compression::Gzip => {
+                    let mut encoder =
+                        flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                    io::copy(&mut input, &mut encoder)?;
+                    encoder.finish()?.flush()?;
+                }
+                Compression::Zstd => {
+                    let mut encoder = zstd::stream::write::Encoder::new(output, 0)?;
+                    io::copy(&mut input, &mut encoder)?;
+                    encoder.finish()?.flush()?;
+                }
+                Compression::None => unreachable!(),
+            }
+            std::fs::rename(&partial, &target)?;
+            std::fs::remove_file(path)
+        })();
+
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result.map(|_| target)
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Gzip => write!(f, "gzip"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(format!("invalid rotation compression: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Compression {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<Compression>().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A rotated archive of the log file found on disk.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// Builds the path an archive of `log_file` covering `label` is renamed to,
+/// e.g. `logs/app.log` and `2024-11-10` give `logs/app.log_2024-11-10.log`.
+pub fn archive_path(log_file: &Path, label: &str, suffix: Option<u32>) -> PathBuf {
+    let mut name = log_file.as_os_str().to_owned();
+    match suffix {
+        Some(n) => name.push(format!("_{}.{}.log", label, n)),
+        None => name.push(format!("_{}.log", label)),
+    }
+    PathBuf::from(name)
+}
+
+/// Returns whether `name` is an archive of the log file named `base`, i.e.
+/// `{base}_{label}[.N].log[.gz|.zst]` where `label` is a timestamp or period.
+fn is_archive_name(base: &str, name: &str) -> bool {
+    let Some(rest) = name
+        .strip_prefix(base)
+        .and_then(|rest| rest.strip_prefix('_'))
+    else {
+        return false;
+    };
+    let rest = rest
+        .strip_suffix(Compression::Gzip.extension())
+        .or_else(|| rest.strip_suffix(Compression::Zstd.extension()))
+        .unwrap_or(rest);
+    let Some(rest) = rest.strip_suffix(".log") else {
+        return false;
+    };
+    let label = match rest.rsplit_once('.') {
+        Some((label, n)) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => label,
+        _ => rest,
+    };
+    label.starts_with(|c: char| c.is_ascii_digit())
+        && label
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '-' || c == '_' || c == 'W')
+}
+
+/// Lists the archives of `log_file` in its parent directory, oldest first.
+pub fn list_archives(log_file: &Path) -> io::Result<Vec<Archive>> {
+    let Some(base) = log_file.file_name().and_then(|n| n.to_str()) else {
+        return Ok(Vec::new());
+    };
+    let dir = match log_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut archives = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let is_archive = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| is_archive_name(base, name));
+        if !is_archive {
+            continue;
+        }
+        // Compression may replace an archive while the directory is listed.
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+        archives.push(Archive {
+            path: entry.path(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            size: metadata.len(),
+        });
+    }
+
+    archives.sort_by(|a, b| {
+        a.modified
+            .cmp(&b.modified)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    Ok(archives)
+}
+
+/// Limits applied to the set of rotated archives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retention {
+    /// Maximum number of archives to keep.
+    pub max_count: Option<usize>,
+    /// Archives last written longer ago than this are deleted.
+    pub max_age: Option<std::time::Duration>,
+    /// Maximum combined size of all archives in bytes.
+    pub max_total_size: Option<u64>,
+}
+
+impl Retention {
+    /// Deletes archives of `log_file` that violate the limits, oldest first,
+    /// leaving room for `incoming` more archives totalling `incoming_size`
+    /// bytes. Archives for which `busy` returns true (still being compressed)
+    /// are left for a later pass. Returns the deleted archives.
+    pub fn apply(
+        &self,
+        log_file: &Path,
+        incoming: usize,
+        incoming_size: u64,
+        busy: impl Fn(&Path) -> bool,
+    ) -> io::Result<Vec<Archive>> {
+        let archives = list_archives(log_file)?;
+        let now = SystemTime::now();
+        let mut count = archives.len() + incoming;
+        let mut total: u64 = archives.iter().map(|a| a.size).sum::<u64>() + incoming_size;
+
+        let mut removed = Vec::new();
+        for archive in archives {
+            let too_many = self.max_count.is_some_and(|max| count > max);
+            let too_big = self.max_total_size.is_some_and(|max| total > max);
+            let too_old = self.max_age.is_some_and(|max| {
+                now.duration_since(archive.modified)
+                    .is_ok_and(|age| age > max)
+            });
+            if !(too_many || too_big || too_old) || busy(&archive.path) {
+                continue;
+            }
+            count -= 1;
+            total = total.saturating_sub(archive.size);
+            match std::fs::remove_file(&archive.path) {
+                Ok(()) => removed.push(archive),
+                // Replaced by its compressed version since it was listed.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+    use std::time::Duration;
+
+    /// Creates `name` in `dir` with `size` bytes, last modified `age` ago.
+    fn touch(dir: &Path, name: &str, size: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![b'x'; size]).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    fn names(archives: &[Archive]) -> Vec<String> {
+        archives
+            .iter()
+            .map(|a| a.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn archive_names() {
+        for name in [
+            "app.log_2024-11-10.log",
+            "app.log_2024-11-10_13.log",
+            "app.log_2024-11-10_13-05.log",
+            "app.log_2024-W45.log",
+            "app.log_20241110_130501.log",
+            "app.log_2024-11-10.2.log",
+            "app.log_2024-11-10.log.gz",
+            "app.log_2024-11-10.2.log.zst",
+        ] {
+            assert!(is_archive_name("app.log", name), "{}", name);
+        }
+        for name in [
+            "app.log",
+            "app.log.bak",
+            "app.log.1",
+            "app.log_backup.log",
+            "app.log_2024-11-10.log.tmp",
+            "app.log_2024-11-10.log.gz.tmp",
+            "app.log_2024-11-10.txt",
+            "app.log_.log",
+            "app.log2_2024-11-10.log",
+            "other.log_2024-11-10.log",
+            "my-app.log_2024-11-10.log",
+        ] {
+            assert!(!is_archive_name("app.log", name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn lists_archives_in_the_parent_directory_oldest_first() {
+        let dir = temp_dir("list-archives").join("logs");
+        std::fs::create_dir(&dir).unwrap();
+        touch(&dir, "app.log", 1, Duration::ZERO);
+        touch(&dir, "app.log_2024-01-03.log.gz", 1, HOUR);
+        touch(&dir, "app.log_2024-01-01.log", 1, 3 * HOUR);
+        touch(&dir, "app.log_2024-01-02.1.log.zst", 1, 2 * HOUR);
+        touch(&dir, "app.log.bak", 1, 4 * HOUR);
+        touch(&dir, "other.log_2024-01-01.log", 1, 4 * HOUR);
+        touch(&dir, "app.log_2024-01-04.log.gz.tmp", 1, 4 * HOUR);
+        std::fs::create_dir(dir.join("app.log_2024-01-05.log")).unwrap();
+
+        let archives = list_archives(&dir.join("app.log")).unwrap();
+        assert_eq!(
+            names(&archives),
+            [
+                "app.log_2024-01-01.log",
+                "app.log_2024-01-02.1.log.zst",
+                "app.log_2024-01-03.log.gz",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let dir = temp_dir("missing-dir");
+        assert!(list_archives(&dir.join("nope").join("app.log")).is_err());
+    }
+
+    #[test]
+    fn retention_deletes_oldest_by_count() {
+        let dir = temp_dir("retention-count");
+        // Name order and age order differ; age decides.
+        touch(&dir, "app.log_2024-01-03.log", 1, 4 * HOUR);
+        touch(&dir, "app.log_2024-01-01.log", 1, 3 * HOUR);
+        touch(&dir, "app.log_2024-01-04.log.gz", 1, 2 * HOUR);
+        touch(&dir, "app.log_2024-01-02.log", 1, HOUR);
+        touch(&dir, "app.log.bak", 1, 10 * HOUR);
+
+        let retention = Retention {
+            max_count: Some(2),
+            ..Retention::default()
+        };
+        let removed = retention
+            .apply(&dir.join("app.log"), 0, 0, |_| false)
+            .unwrap();
+        assert_eq!(
+            names(&removed),
+            ["app.log_2024-01-03.log", "app.log_2024-01-01.log"]
+        );
+        assert_eq!(
+            names(&list_archives(&dir.join("app.log")).unwrap()),
+            ["app.log_2024-01-04.log.gz", "app.log_2024-01-02.log"]
+        );
+        assert!(dir.join("app.log.bak").exists());
+
+        // Room for one incoming archive leaves a single one.
+        let removed = retention
+            .apply(&dir.join("app.log"), 1, 1, |_| false)
+            .unwrap();
+        assert_eq!(names(&removed), ["app.log_2024-01-04.log.gz"]);
+    }
+
+    #[test]
+    fn retention_skips_busy_and_vanished_archives() {
+        let dir = temp_dir("retention-busy");
+        let compressing = touch(&dir, "app.log_2024-01-01.log", 1, 48 * HOUR);
+        touch(&dir, "app.log_2024-01-02.log", 1, 25 * HOUR);
+        touch(&dir, "app.log_2024-01-03.log", 1, HOUR);
+
+        let retention = Retention {
+            max_age: Some(24 * HOUR),
+            ..Retention::default()
+        };
+        let removed = retention
+            .apply(&dir.join("app.log"), 0, 0, |archive| archive == compressing)
+            .unwrap();
+        assert_eq!(names(&removed), ["app.log_2024-01-02.log"]);
+        assert!(compressing.exists());
+
+        // An archive that disappears between listing and deleting is not an
+        // error, and is not reported as deleted.
+        let removed = retention
+            .apply(&dir.join("app.log"), 0, 0, |archive| {
+                let _ = std::fs::remove_file(archive);
+                false
+            })
+            .unwrap();
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn retention_deletes_by_age() {
+        let dir = temp_dir("retention-age");
+        touch(&dir, "app.log_2024-01-01.log", 1, 48 * HOUR);
+        touch(&dir, "app.log_2024-01-02.log", 1, 25 * HOUR);
+        touch(&dir, "app.log_2024-01-03.log", 1, HOUR);
+        touch(&dir, "app.log.bak", 1, 48 * HOUR);
+
+        let retention = Retention {
+            max_age: Some(24 * HOUR),
+            ..Retention::default()
+        };
+        let removed = retention
+            .apply(&dir.join("app.log"), 0, 0, |_| false)
+            .unwrap();
+        assert_eq!(
+            names(&removed),
+            ["app.log_2024-01-01.log", "app.log_2024-01-02.log"]
+        );
+        assert!(dir.join("app.log_2024-01-03.log").exists());
+        assert!(dir.join("app.log.bak").exists());
+    }
+
+    #[test]
+    fn retention_deletes_oldest_by_total_size() {
+        let dir = temp_dir("retention-size");
+        touch(&dir, "app.log_2024-01-01.log", 10, 3 * HOUR);
+        touch(&dir, "app.log_2024-01-02.log.gz", 10, 2 * HOUR);
+        touch(&dir, "app.log_2024-01-03.log", 10, HOUR);
+        touch(&dir, "app.log.bak", 100, 4 * HOUR);
+
+        let retention = Retention {
+            max_total_size: Some(25),
+            ..Retention::default()
+        };
+        let removed = retention
+            .apply(&dir.join("app.log"), 0, 0, |_| false)
+            .unwrap();
+        assert_eq!(names(&removed), ["app.log_2024-01-01.log"]);
+
+        // An incoming 10 byte archive pushes the total over again.
+        let removed = retention
+            .apply(&dir.join("app.log"), 1, 10, |_| false)
+            .unwrap();
+        assert_eq!(names(&removed), ["app.log_2024-01-02.log.gz"]);
+        assert!(dir.join("app.log_2024-01-03.log").exists());
+        assert!(dir.join("app.log.bak").exists());
+    }
+
+    fn at(s: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(s).unwrap()
+    }
+
+    fn interval(s: &str) -> RotationInterval {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn rotation_intervals_parse() {
+        for spec in [
+            "hourly",
+            "Daily",
+            "WEEKLY",
+            "* * * * *",
+            "*/15 9-17 * * 1-5",
+            "0 0 1,15 * *",
+            "30 2 * * 0",
+            "0 0 * * 7",
+            "0 0 29 2 *",
+            "0 0 31 2 5",
+        ] {
+            assert!(spec.parse::<RotationInterval>().is_ok(), "{:?}", spec);
+        }
+        for spec in [
+            "",
+            "monthly",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+            "0 0 31 2 *",
+            "0 0 30,31 2 *",
+            "0 0 31 4,6,9,11 *",
+        ] {
+            assert!(spec.parse::<RotationInterval>().is_err(), "{:?}", spec);
+        }
+        assert_eq!(
+            "0 0 31 2 *".parse::<RotationInterval>().unwrap_err(),
+            "invalid cron spec \"0 0 31 2 *\": never fires"
+        );
+    }
+
+    #[test]
+    fn rotation_periods() {
+        let table = [
+            (
+                "hourly",
+                "2024-11-10T13:05:30+02:00",
+                "2024-11-10T13:00:00+02:00",
+                Some("2024-11-10T14:00:00+02:00"),
+            ),
+            (
+                "daily",
+                "2024-12-31T23:59:59-05:00",
+                "2024-12-31T00:00:00-05:00",
+                Some("2025-01-01T00:00:00-05:00"),
+            ),
+            // 2024-11-10 is a Sunday; weeks start on Monday.
+            (
+                "weekly",
+                "2024-11-10T13:05:00+00:00",
+                "2024-11-04T00:00:00+00:00",
+                Some("2024-11-11T00:00:00+00:00"),
+            ),
+            (
+                "*/15 * * * *",
+                "2024-11-10T13:05:30+00:00",
+                "2024-11-10T13:00:00+00:00",
+                Some("2024-11-10T13:15:00+00:00"),
+            ),
+            // A fire time starts the period it falls on.
+            (
+                "*/15 * * * *",
+                "2024-11-10T13:15:00+00:00",
+                "2024-11-10T13:15:00+00:00",
+                Some("2024-11-10T13:30:00+00:00"),
+            ),
+            (
+                "30 2 * * 1-5",
+                "2024-11-09T12:00:00+00:00",
+                "2024-11-08T02:30:00+00:00",
+                Some("2024-11-11T02:30:00+00:00"),
+            ),
+            (
+                "0 0 1 1 *",
+                "2024-11-10T13:05:00+00:00",
+                "2024-01-01T00:00:00+00:00",
+                Some("2025-01-01T00:00:00+00:00"),
+            ),
+            (
+                "0 0 29 2 *",
+                "2025-03-01T00:00:00+00:00",
+                "2024-02-29T00:00:00+00:00",
+                Some("2028-02-29T00:00:00+00:00"),
+            ),
+            // Both day fields restricted: the 13th or any Friday.
+            (
+                "0 0 13 * 5",
+                "2024-09-10T00:00:00+00:00",
+                "2024-09-06T00:00:00+00:00",
+                Some("2024-09-13T00:00:00+00:00"),
+            ),
+            (
+                "0 0 13 * 5",
+                "2024-09-13T12:00:00+00:00",
+                "2024-09-13T00:00:00+00:00",
+                Some("2024-09-20T00:00:00+00:00"),
+            ),
+            (
+                "59 23 31 12 *",
+                "2024-06-01T00:00:00+09:30",
+                "2023-12-31T23:59:00+09:30",
+                Some("2024-12-31T23:59:00+09:30"),
+            ),
+        ];
+        for (spec, now, start, end) in table {
+            let period = interval(spec).period(at(now));
+            assert_eq!(period.start, at(start), "{} at {}", spec, now);
+            assert_eq!(period.end, end.map(at), "{} at {}", spec, now);
+            assert_eq!(period.start.offset(), at(now).offset());
+        }
+    }
+
+    #[test]
+    fn rotation_labels() {
+        let start = at("2024-11-04T00:00:00+02:00");
+        let table = [
+            ("hourly", "2024-11-04_00"),
+            ("daily", "2024-11-04"),
+            ("weekly", "2024-W45"),
+            ("0 0 * * 1", "2024-11-04_00-00"),
+        ];
+        for (spec, label) in table {
+            assert_eq!(interval(spec).label(start), label, "{}", spec);
+            assert!(is_archive_name(
+                "app.log",
+                &format!("app.log_{}.log", label)
+            ));
+        }
+        // ISO weeks belong to the year of their Thursday.
+        let start = at("2024-12-30T00:00:00+00:00");
+        assert_eq!(interval("weekly").label(start), "2025-W01");
+    }
+}
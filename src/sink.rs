@@ -0,0 +1,316 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+
+use crate::config::{LogFormat, Settings};
+use crate::filter::LevelFilters;
+use crate::journald::JournaldConfig;
+use crate::syslog::SyslogConfig;
+use crate::template::{self, Template};
+use crate::LogLevel;
+
+/// Which standard stream console output goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConsoleStream {
+    #[default]
+    Stdout,
+    Stderr,
+    /// WARN and above to stderr, everything else to stdout.
+    Split,
+}
+
+impl ConsoleStream {
+    fn is_stderr(&self, level: &LogLevel) -> bool {
+        match self {
+            ConsoleStream::Stdout => false,
+            ConsoleStream::Stderr => true,
+            ConsoleStream::Split => level.is_at_least(&LogLevel::Warn),
+        }
+    }
+
+    /// Whether the stream a record at `level` goes to is a terminal.
+    pub fn is_terminal(&self, level: &LogLevel) -> bool {
+        if self.is_stderr(level) {
+            io::stderr().is_terminal()
+        } else {
+            io::stdout().is_terminal()
+        }
+    }
+
+    /// Writes `line` to the stream for `level`, holding the lock for the
+    /// whole line so concurrent lines do not interleave.
+    pub fn write_line(&self, level: &LogLevel, line: &str) -> io::Result<()> {
+        if self.is_stderr(level) {
+            writeln!(io::stderr().lock(), "{}", line)
+        } else {
+            writeln!(io::stdout().lock(), "{}", line)
+        }
+    }
+}
+
+impl std::fmt::Display for ConsoleStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConsoleStream::Stdout => write!(f, "stdout"),
+            ConsoleStream::Stderr => write!(f, "stderr"),
+            ConsoleStream::Split => write!(f, "split"),
+        }
+    }
+}
+
+impl FromStr for ConsoleStream {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "stdout" => Ok(ConsoleStream::Stdout),
+            "stderr" => Ok(ConsoleStream::Stderr),
+            "split" => Ok(ConsoleStream::Split),
+            _ => Err(format!("invalid console stream: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for ConsoleStream {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConsoleStream {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<ConsoleStream>().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Whether output is colored.
+///
+/// `Auto` defers to the environment: a non-empty `NO_COLOR` disables colors,
+/// `FORCE_COLOR` or `CLICOLOR_FORCE` (other than `0`) enables them and
+/// `CLICOLOR=0` disables them. Otherwise colors are used only when the output
+/// is a terminal. `Always` and `Never` ignore the environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorMode {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+fn env_flag(name: &str) -> Option<bool> {
+    let value = std::env::var(name).ok().filter(|v| !v.is_empty())?;
+    Some(!matches!(value.to_lowercase().as_str(), "0" | "false"))
+}
+
+impl ColorMode {
+    /// Settles `Auto` from the environment where the environment decides.
+    fn settle(self) -> Self {
+        if self != ColorMode::Auto {
+            return self;
+        }
+        if env_flag("NO_COLOR").is_some() {
+            return ColorMode::Never;
+        }
+        match env_flag("FORCE_COLOR").or(env_flag("CLICOLOR_FORCE").filter(|force| *force)) {
+            Some(true) => return ColorMode::Always,
+            Some(false) => return ColorMode::Never,
+            None => {}
+        }
+        if env_flag("CLICOLOR") == Some(false) {
+            return ColorMode::Never;
+        }
+        ColorMode::Auto
+    }
+
+    /// Whether to color output going to a destination that is (or is not) a terminal.
+    pub fn enabled(&self, terminal: bool) -> bool {
+        match self {
+            ColorMode::Auto => terminal,
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+        }
+    }
+}
+
+impl std::fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ColorMode::Auto => write!(f, "auto"),
+            ColorMode::Always => write!(f, "always"),
+            ColorMode::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(ColorMode::Auto),
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            _ => Err(format!("invalid color mode: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for ColorMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ColorMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Flag(bool),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Flag(true) => Ok(ColorMode::Always),
+            Raw::Flag(false) => Ok(ColorMode::Never),
+            Raw::Text(s) => s.parse::<ColorMode>().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Where a sink writes, tagged by `type` in the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    Console {
+        #[serde(default)]
+        stream: ConsoleStream,
+    },
+    File {
+        path: String,
+    },
+    Syslog(SyslogConfig),
+    Journald(JournaldConfig),
+}
+
+/// An entry of `sinks`.
+#[derive(Serialize, Deserialize)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+
+    /// Falls back to the top-level `level_filters` and `minimum_log_level`.
+    #[serde(default)]
+    pub minimum_level: Option<LogLevel>,
+
+    #[serde(default)]
+    pub level_filters: BTreeMap<String, LogLevel>,
+
+    /// `text` or `json`; only used by console and file sinks.
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// Line template for the `text` format.
+    #[serde(default)]
+    pub template: Option<String>,
+
+    /// `auto`, `always` or `never`. Defaults to `auto` on the console and
+    /// `never` in files.
+    #[serde(default)]
+    pub color: Option<ColorMode>,
+}
+
+/// A validated sink.
+pub struct Sink {
+    pub kind: SinkKind,
+    pub minimum_level: Option<LogLevel>,
+    pub level_filters: LevelFilters,
+    pub format: LogFormat,
+    pub template: Template,
+    /// With `Auto` already settled from the environment where it decides.
+    pub color: ColorMode,
+}
+
+impl Sink {
+    /// A sink without filters of its own, as described by the flat config fields.
+    pub fn new(kind: SinkKind, format: LogFormat, template: Template, color: ColorMode) -> Self {
+        Sink {
+            kind,
+            minimum_level: None,
+            level_filters: LevelFilters::default(),
+            format,
+            template,
+            color: color.settle(),
+        }
+    }
+
+    pub fn from_config(config: SinkConfig) -> Result<Self, String> {
+        let template = Template::parse(
+            config
+                .template
+                .as_deref()
+                .unwrap_or(template::DEFAULT_FORMAT),
+        )?;
+        let color = config.color.unwrap_or(match config.kind {
+            SinkKind::Console { .. } => ColorMode::Auto,
+            _ => ColorMode::Never,
+        });
+        Ok(Sink {
+            kind: config.kind,
+            minimum_level: config.minimum_level,
+            level_filters: LevelFilters::new(&config.level_filters),
+            format: config.format,
+            template,
+            color: color.settle(),
+        })
+    }
+
+    /// Whether a record at `level` for `target` goes to this sink. The sink's
+    /// own filters win over its `minimum_level`, which wins over the
+    /// top-level settings.
+    pub fn enabled(&self, level: &LogLevel, target: &str, settings: &Settings) -> bool {
+        let minimum = self
+            .level_filters
+            .level_for(target)
+            .or(self.minimum_level.as_ref())
+            .or(settings.level_filters.level_for(target))
+            .unwrap_or(&settings.minimum_log_level);
+        level.is_at_least(minimum)
+    }
+
+    /// The log file path of a file sink.
+    pub fn file_path(&self) -> Option<&str> {
+        match &self.kind {
+            SinkKind::File { path } => Some(path),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::custom;
+
+    #[test]
+    fn split_routes_by_severity_only() {
+        let split = ConsoleStream::Split;
+        assert!(!split.is_stderr(&LogLevel::Info));
+        assert!(split.is_stderr(&LogLevel::Warn));
+        assert!(split.is_stderr(&LogLevel::Fatal));
+        for name in ["AAA", "ZZZ"] {
+            assert!(split.is_stderr(&custom(name, 13)), "{}", name);
+            assert!(!split.is_stderr(&custom(name, 12)), "{}", name);
+        }
+        assert!(ConsoleStream::Stderr.is_stderr(&LogLevel::Trace));
+        assert!(!ConsoleStream::Stdout.is_stderr(&LogLevel::Fatal));
+    }
+}
@@ -0,0 +1,774 @@
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::fields;
+use crate::template::{self, Record};
+
+/// How syslog messages leave the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyslogTransport {
+    /// A local Unix datagram socket, `/dev/log` by default.
+    #[default]
+    Unix,
+    Udp,
+    /// TCP with octet-counting framing (RFC 6587).
+    Tcp,
+}
+
+impl std::fmt::Display for SyslogTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SyslogTransport::Unix => write!(f, "unix"),
+            SyslogTransport::Udp => write!(f, "udp"),
+            SyslogTransport::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+impl FromStr for SyslogTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "unix" => Ok(SyslogTransport::Unix),
+            "udp" => Ok(SyslogTransport::Udp),
+            "tcp" => Ok(SyslogTransport::Tcp),
+            _ => Err(format!("invalid syslog transport: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for SyslogTransport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SyslogTransport {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<SyslogTransport>()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The syslog message format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyslogFormat {
+    #[default]
+    Rfc5424,
+    /// The older BSD format, for relays that do not understand RFC 5424.
+    Rfc3164,
+}
+
+impl std::fmt::Display for SyslogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SyslogFormat::Rfc5424 => write!(f, "rfc5424"),
+            SyslogFormat::Rfc3164 => write!(f, "rfc3164"),
+        }
+    }
+}
+
+impl FromStr for SyslogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rfc5424" => Ok(SyslogFormat::Rfc5424),
+            "rfc3164" => Ok(SyslogFormat::Rfc3164),
+            _ => Err(format!("invalid syslog format: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for SyslogFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SyslogFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<SyslogFormat>().map_err(serde::de::Error::custom)
+    }
+}
+
+const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// A syslog facility, written by name (`user`, `daemon`, `local0`, ...).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Facility(u8);
+
+impl Default for Facility {
+    fn default() -> Self {
+        Facility(1)
+    }
+}
+
+impl std::fmt::Display for Facility {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", FACILITIES[self.0 as usize])
+    }
+}
+
+impl FromStr for Facility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FACILITIES
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(s))
+            .map(|code| Facility(code as u8))
+            .ok_or_else(|| format!("invalid syslog facility: {:?}", s))
+    }
+}
+
+impl Serialize for Facility {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Facility {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<Facility>().map_err(serde::de::Error::custom)
+    }
+}
+
+fn default_sd_id() -> String {
+    "fields@32473".to_string()
+}
+
+/// The `syslog` section of the config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyslogConfig {
+    #[serde(default)]
+    pub transport: SyslogTransport,
+
+    /// Socket path for `unix` (default `/dev/log`), `host:port` for `udp` and
+    /// `tcp` (default `127.0.0.1:514`).
+    #[serde(default)]
+    pub address: Option<String>,
+
+    #[serde(default)]
+    pub format: SyslogFormat,
+
+    #[serde(default)]
+    pub facility: Facility,
+
+    /// SD-ID of the RFC 5424 structured-data element that carries the fields.
+    #[serde(default = "default_sd_id")]
+    pub structured_data_id: String,
+}
+
+impl SyslogConfig {
+    pub fn address(&self) -> &str {
+        match (&self.address, self.transport) {
+            (Some(address), _) => address,
+            (None, SyslogTransport::Unix) => "/dev/log",
+            (None, _) => "127.0.0.1:514",
+        }
+    }
+
+    /// Describes the destination for error messages, e.g. `syslog udp 127.0.0.1:514`.
+    pub fn describe(&self) -> String {
+        format!("syslog {} {}", self.transport, self.address())
+    }
+}
+
+/// Bounds connecting and writing, which happen inside `execute` while the
+/// sink is locked, so a stuck collector cannot hang every thread that logs.
+pub(crate) const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a sink waits before reconnecting after a failed connect,
+/// doubling with every failure up to the maximum.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// An open socket to the daemon.
+enum Socket {
+    Unix(UnixDatagram),
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("{} did not resolve to any address", address),
+    );
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, SEND_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(SEND_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+impl Socket {
+    fn open(transport: SyslogTransport, address: &str) -> io::Result<Self> {
+        match transport {
+            SyslogTransport::Unix => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(address)?;
+                socket.set_write_timeout(Some(SEND_TIMEOUT))?;
+                Ok(Socket::Unix(socket))
+            }
+            SyslogTransport::Udp => {
+                let bind = if address.starts_with('[') {
+                    "[::]:0"
+                } else {
+                    "0.0.0.0:0"
+                };
+                let socket = UdpSocket::bind(bind)?;
+                socket.connect(address)?;
+                Ok(Socket::Udp(socket))
+            }
+            SyslogTransport::Tcp => connect_tcp(address).map(Socket::Tcp),
+        }
+    }
+
+    fn write(&mut self, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Socket::Unix(socket) => socket.send(payload).map(|_| ()),
+            Socket::Udp(socket) => socket.send(payload).map(|_| ()),
+            Socket::Tcp(stream) => stream.write_all(payload),
+        }
+    }
+}
+
+/// The socket to the daemon, reopened on the next send after a failure.
+struct Connection {
+    transport: SyslogTransport,
+    socket: Option<Socket>,
+    /// Until then sends fail without trying to connect.
+    retry_at: Option<Instant>,
+    delay: Duration,
+}
+
+impl Connection {
+    fn new(transport: SyslogTransport) -> Self {
+        Connection {
+            transport,
+            socket: None,
+            retry_at: None,
+            delay: MIN_RECONNECT_DELAY,
+        }
+    }
+
+    fn send(&mut self, address: &str, payload: &[u8]) -> io::Result<()> {
+        if let Some(socket) = &mut self.socket {
+            match socket.write(payload) {
+                Ok(()) => return Ok(()),
+                // The daemon stopped reading; a new connection would fill up as well.
+                Err(e) if is_timeout(&e) => {
+                    self.socket = None;
+                    return Err(self.back_off(e));
+                }
+                // The daemon may have restarted; try once with a new connection.
+                Err(_) => self.socket = None,
+            }
+        }
+        if self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "not connected, waiting to reconnect",
+            ));
+        }
+
+        let sent = Socket::open(self.transport, address).and_then(|mut socket| {
+            socket.write(payload)?;
+            Ok(socket)
+        });
+        match sent {
+            Ok(socket) => {
+                self.socket = Some(socket);
+                self.retry_at = None;
+                self.delay = MIN_RECONNECT_DELAY;
+                Ok(())
+            }
+            Err(e) => Err(self.back_off(e)),
+        }
+    }
+
+    fn back_off(&mut self, error: io::Error) -> io::Error {
+        self.retry_at = Some(Instant::now() + self.delay);
+        self.delay = (self.delay * 2).min(MAX_RECONNECT_DELAY);
+        error
+    }
+}
+
+/// A connection to a syslog daemon.
+pub struct SyslogSink {
+    config: SyslogConfig,
+    connection: Connection,
+}
+
+impl SyslogSink {
+    pub fn connect(config: &SyslogConfig) -> io::Result<Self> {
+        let mut connection = Connection::new(config.transport);
+        // Datagram sockets connect up front so a bad address is reported at
+        // once; TCP connects lazily, so a collector that is down is retried
+        // with a backoff.
+        if config.transport != SyslogTransport::Tcp {
+            connection.socket = Some(Socket::open(config.transport, config.address())?);
+        }
+        Ok(SyslogSink {
+            config: config.clone(),
+            connection,
+        })
+    }
+
+    pub fn config(&self) -> &SyslogConfig {
+        &self.config
+    }
+
+    pub fn send(&mut self, record: &Record, time: DateTime<FixedOffset>) -> io::Result<()> {
+        let message = match self.config.format {
+            SyslogFormat::Rfc5424 => format_rfc5424(&self.config, record, time),
+            SyslogFormat::Rfc3164 => format_rfc3164(&self.config, record, time),
+        };
+        let payload = match self.config.transport {
+            SyslogTransport::Tcp => format!("{} {}", message.len(), message),
+            _ => message,
+        };
+        self.connection
+            .send(self.config.address(), payload.as_bytes())
+    }
+}
+
+fn priority(config: &SyslogConfig, record: &Record) -> u8 {
+    config.facility.0 * 8 + record.level.syslog_severity()
+}
+
+/// Keeps printable US-ASCII except `except`, truncated to `max` characters,
+/// or `-` (the RFC 5424 nil value) if nothing is left.
+fn header_field(value: &str, max: usize, except: &[char]) -> String {
+    let field: String = value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && !except.contains(&c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(max)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+fn hostname() -> String {
+    header_field(template::hostname(), 255, &[])
+}
+
+fn escape_param(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn structured_data(config: &SyslogConfig, record: &Record) -> String {
+    let params = fields::flatten(record.fields);
+    if params.is_empty() {
+        return "-".to_string();
+    }
+    let sd_name = |name: &str| header_field(name, 32, &['=', ']', '"']);
+    let mut sd = format!("[{}", sd_name(&config.structured_data_id));
+    for (key, value) in params {
+        let value = match value {
+            serde_json::Value::String(s) => s,
+            value => value.to_string(),
+        };
+        sd.push_str(&format!(" {}=\"{}\"", sd_name(&key), escape_param(&value)));
+    }
+    sd.push(']');
+    sd
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG`, with
+/// the sub-app as MSGID.
+fn format_rfc5424(config: &SyslogConfig, record: &Record, time: DateTime<FixedOffset>) -> String {
+    format!(
+        "<{}>1 {} {} {} {} {} {} {}",
+        priority(config, record),
+        time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        hostname(),
+        header_field(record.app, 48, &[]),
+        std::process::id(),
+        header_field(record.sub_app.unwrap_or(""), 32, &[]),
+        structured_data(config, record),
+        record.message,
+    )
+}
+
+/// `<PRI>Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG`. RFC 3164 has no place for
+/// the sub-app or fields, so they are prepended and appended to the message.
+fn format_rfc3164(config: &SyslogConfig, record: &Record, time: DateTime<FixedOffset>) -> String {
+    let mut message = match record.sub_app {
+        Some(sub_app) => format!("{}: {}", sub_app, record.message),
+        None => record.message.to_string(),
+    };
+    let pairs = fields::render_pairs(record.fields);
+    if !pairs.is_empty() {
+        message.push(' ');
+        message.push_str(&pairs);
+    }
+    format!(
+        "<{}>{} {} {}[{}]: {}",
+        priority(config, record),
+        time.format("%b %e %H:%M:%S"),
+        hostname(),
+        header_field(record.app, 32, &['[', ']', ':']),
+        std::process::id(),
+        message,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+    use crate::LogLevel;
+    use serde_json::{json, Map, Value};
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn config(transport: SyslogTransport, address: String, format: SyslogFormat) -> SyslogConfig {
+        SyslogConfig {
+            transport,
+            address: Some(address),
+            format,
+            facility: "local0".parse().unwrap(),
+            structured_data_id: default_sd_id(),
+        }
+    }
+
+    fn time() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2024-11-05T13:05:01.5+02:00").unwrap()
+    }
+
+    fn send(
+        sink: &mut SyslogSink,
+        level: LogLevel,
+        message: &str,
+        fields: Value,
+    ) -> io::Result<()> {
+        let Value::Object(fields) = fields else {
+            panic!("fields must be an object");
+        };
+        sink.send(&record(&level, message, &fields), time())
+    }
+
+    fn record<'a>(
+        level: &'a LogLevel,
+        message: &'a str,
+        fields: &'a Map<String, Value>,
+    ) -> Record<'a> {
+        Record {
+            timestamp: "",
+            level,
+            app: "deploy",
+            sub_app: Some("db"),
+            target: "deploy -> db",
+            message,
+            fields,
+            seq: 1,
+        }
+    }
+
+    #[test]
+    fn rfc5424_over_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Udp,
+            address,
+            SyslogFormat::Rfc5424,
+        ))
+        .unwrap();
+
+        let fields = json!({"user": "ann", "job": {"id": 4}, "note": "a \"b\" [c] \\d"});
+        send(&mut sink, LogLevel::Warn, "disk low", fields).unwrap();
+        let mut buf = [0u8; 2048];
+        let len = server.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+
+        // local0 (16) * 8 + warning (4)
+        let expected = format!(
+            "<132>1 2024-11-05T13:05:01.500000+02:00 {} deploy {} db \
+             [fields@32473 user=\"ann\" job.id=\"4\" note=\"a \\\"b\\\" [c\\] \\\\d\"] disk low",
+            hostname(),
+            std::process::id(),
+        );
+        assert_eq!(message, expected);
+
+        // Without fields the structured data is the nil value.
+        send(&mut sink, LogLevel::Fatal, "down", json!({})).unwrap();
+        let len = server.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.starts_with("<130>1 "), "{}", message);
+        assert!(message.ends_with(" db - down"), "{}", message);
+    }
+
+    #[test]
+    fn structured_data_names_are_sanitized() {
+        let fields = json!({"a b=c]\"d": 1});
+        let Value::Object(fields) = fields else {
+            unreachable!()
+        };
+        let mut config = config(SyslogTransport::Udp, String::new(), SyslogFormat::Rfc5424);
+        config.structured_data_id = "my id@1".to_string();
+        let level = LogLevel::Info;
+        assert_eq!(
+            structured_data(&config, &record(&level, "", &fields)),
+            "[my_id@1 a_b_c__d=\"1\"]"
+        );
+    }
+
+    #[test]
+    fn rfc3164_over_unix() {
+        let path = temp_dir("syslog-unix").join("log.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = path.to_str().unwrap().to_string();
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Unix,
+            address,
+            SyslogFormat::Rfc3164,
+        ))
+        .unwrap();
+
+        send(
+            &mut sink,
+            LogLevel::Info,
+            "started",
+            json!({"run": 7, "mode": "full sync"}),
+        )
+        .unwrap();
+        let mut buf = [0u8; 2048];
+        let len = server.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+
+        // local0 (16) * 8 + informational (6)
+        let expected = format!(
+            "<134>Nov  5 13:05:01 {} deploy[{}]: db: started run=7 mode=\"full sync\"",
+            hostname(),
+            std::process::id(),
+        );
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn unix_reconnects_after_the_daemon_restarts() {
+        let path = temp_dir("syslog-unix-restart").join("log.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+        let address = path.to_str().unwrap().to_string();
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Unix,
+            address,
+            SyslogFormat::Rfc3164,
+        ))
+        .unwrap();
+        send(&mut sink, LogLevel::Info, "one", json!({})).unwrap();
+
+        drop(server);
+        std::fs::remove_file(&path).unwrap();
+        send(&mut sink, LogLevel::Info, "two", json!({})).unwrap_err();
+
+        // Within the delay, sends fail without trying to connect.
+        let server = UnixDatagram::bind(&path).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let waiting = send(&mut sink, LogLevel::Info, "three", json!({})).unwrap_err();
+        assert_eq!(waiting.kind(), io::ErrorKind::NotConnected);
+
+        std::thread::sleep(MIN_RECONNECT_DELAY);
+        send(&mut sink, LogLevel::Info, "four", json!({})).unwrap();
+        let mut buf = [0u8; 2048];
+        let len = server.recv(&mut buf).unwrap();
+        let message = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(message.ends_with(": db: four"), "{}", message);
+    }
+
+    /// Reads everything sent to `listener` by one client until it disconnects.
+    fn accept_all(listener: TcpListener) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            received
+        })
+    }
+
+    #[test]
+    fn tcp_uses_octet_counting() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = accept_all(listener);
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Tcp,
+            address,
+            SyslogFormat::Rfc5424,
+        ))
+        .unwrap();
+
+        send(&mut sink, LogLevel::Error, "first", json!({})).unwrap();
+        send(
+            &mut sink,
+            LogLevel::Debug,
+            "second\nline",
+            json!({"k": "é"}),
+        )
+        .unwrap();
+        drop(sink);
+        let received = server.join().unwrap();
+
+        let mut rest = received.as_str();
+        let mut frames = Vec::new();
+        while !rest.is_empty() {
+            let (len, after) = rest.split_once(' ').unwrap();
+            let len: usize = len.parse().unwrap();
+            frames.push(&after[..len]);
+            rest = &after[len..];
+        }
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].starts_with("<131>1 "), "{}", frames[0]);
+        assert!(frames[0].ends_with(" first"), "{}", frames[0]);
+        assert!(frames[1].starts_with("<135>1 "), "{}", frames[1]);
+        assert!(
+            frames[1].ends_with(" [fields@32473 k=\"é\"] second\nline"),
+            "{}",
+            frames[1]
+        );
+    }
+
+    #[test]
+    fn tcp_backs_off_after_a_failed_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        // The collector being down does not keep the sink from being created.
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Tcp,
+            address.clone(),
+            SyslogFormat::Rfc5424,
+        ))
+        .unwrap();
+        let refused = send(&mut sink, LogLevel::Info, "one", json!({})).unwrap_err();
+        assert_eq!(refused.kind(), io::ErrorKind::ConnectionRefused);
+
+        // Within the delay, sends fail without trying to connect.
+        let listener = TcpListener::bind(&address).unwrap();
+        let waiting = send(&mut sink, LogLevel::Info, "two", json!({})).unwrap_err();
+        assert_eq!(waiting.kind(), io::ErrorKind::NotConnected);
+
+        std::thread::sleep(MIN_RECONNECT_DELAY);
+        let server = accept_all(listener);
+        send(&mut sink, LogLevel::Info, "three", json!({})).unwrap();
+        drop(sink);
+        let received = server.join().unwrap();
+        assert!(received.ends_with(" three"), "{}", received);
+    }
+
+    #[test]
+    fn tcp_write_times_out_when_the_collector_stops_reading() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut sink = SyslogSink::connect(&config(
+            SyslogTransport::Tcp,
+            address,
+            SyslogFormat::Rfc5424,
+        ))
+        .unwrap();
+
+        // Accepted but never read, so the socket buffers fill up.
+        let message = "x".repeat(64 * 1024);
+        let started = Instant::now();
+        let error = loop {
+            if let Err(e) = send(&mut sink, LogLevel::Info, &message, json!({})) {
+                break e;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(30),
+                "never timed out"
+            );
+        };
+        assert!(is_timeout(&error), "{:?}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        // The next record does not wait for the stuck collector again.
+        let waiting = send(&mut sink, LogLevel::Info, "next", json!({})).unwrap_err();
+        assert_eq!(waiting.kind(), io::ErrorKind::NotConnected);
+        drop(listener);
+    }
+}
@@ -0,0 +1,271 @@
+use colored::*;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Once};
+
+use crate::fields;
+use crate::LogLevel;
+
+/// Layout used when no `console_format`/`file_format` is configured.
+pub const DEFAULT_FORMAT: &str = "[{timestamp}] [{level}] {target}: {message}{fields}";
+
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Sinks decide whether to color, so `colored`'s own terminal and environment
+/// detection is switched off the first time colored output is rendered.
+static COLOR_OVERRIDE: Once = Once::new();
+
+static HOSTNAME: LazyLock<String> = LazyLock::new(|| {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+});
+
+/// Returns the next value of the process-wide record sequence number.
+pub fn next_seq() -> u64 {
+    SEQ.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+pub fn hostname() -> &'static str {
+    &HOSTNAME
+}
+
+/// Everything a template can refer to for a single log line.
+pub struct Record<'a> {
+    pub timestamp: &'a str,
+    pub level: &'a LogLevel,
+    pub app: &'a str,
+    pub sub_app: Option<&'a str>,
+    pub target: &'a str,
+    pub message: &'a str,
+    pub fields: &'a Map<String, Value>,
+    pub seq: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Placeholder {
+    Timestamp,
+    Level,
+    Severity,
+    SyslogSeverity,
+    App,
+    SubApp,
+    Target,
+    Message,
+    Field(String),
+    Fields,
+    Pid,
+    Hostname,
+    Seq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Align {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder {
+        placeholder: Placeholder,
+        width: usize,
+        align: Align,
+    },
+}
+
+/// A parsed line template such as `[{timestamp}] [{level:5}] {app}: {message}`.
+///
+/// Supported placeholders are `{timestamp}`, `{level}`, `{severity}` (the
+/// OpenTelemetry severity number), `{syslog_severity}`, `{app}`, `{sub_app}`,
+/// `{target}` (app and sub-app joined with ` -> `), `{message}`,
+/// `{field.NAME}`, `{fields}` (all fields as ` key=value` pairs, each preceded
+/// by a space), `{pid}`, `{hostname}` and `{seq}`. Any placeholder accepts a
+/// width such as `{level:5}` or `{level:>5}`; `{{` and `}}` are literal braces.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => spec.push(c),
+                            None => return Err(format!("unclosed placeholder {{{}", spec)),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(&spec)?);
+                }
+                '}' => return Err("unmatched '}' (use '}}' for a literal brace)".to_string()),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template { segments })
+    }
+
+    pub fn render(&self, record: &Record, colored: bool) -> String {
+        if colored {
+            COLOR_OVERRIDE.call_once(|| colored::control::set_override(true));
+        }
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Placeholder {
+                    placeholder,
+                    width,
+                    align,
+                } => {
+                    let value = placeholder_value(placeholder, record);
+                    let value = match align {
+                        Align::Left => format!("{:<width$}", value, width = width),
+                        Align::Right => format!("{:>width$}", value, width = width),
+                    };
+                    if colored {
+                        out.push_str(&colorize(placeholder, record, value).to_string());
+                    } else {
+                        out.push_str(&value);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+fn parse_placeholder(spec: &str) -> Result<Segment, String> {
+    let (name, width_spec) = match spec.split_once(':') {
+        Some((name, width)) => (name, Some(width)),
+        None => (spec, None),
+    };
+
+    let placeholder = match name {
+        "timestamp" => Placeholder::Timestamp,
+        "level" => Placeholder::Level,
+        "severity" => Placeholder::Severity,
+        "syslog_severity" => Placeholder::SyslogSeverity,
+        "app" => Placeholder::App,
+        "sub_app" => Placeholder::SubApp,
+        "target" => Placeholder::Target,
+        "message" => Placeholder::Message,
+        "fields" => Placeholder::Fields,
+        "pid" => Placeholder::Pid,
+        "hostname" => Placeholder::Hostname,
+        "seq" => Placeholder::Seq,
+        _ => match name.strip_prefix("field.") {
+            Some(field) if !field.is_empty() => Placeholder::Field(field.to_string()),
+            _ => return Err(format!("unknown placeholder {{{}}}", spec)),
+        },
+    };
+
+    let (align, width) = match width_spec {
+        None => (Align::Left, 0),
+        Some(w) => {
+            let (align, digits) = if let Some(d) = w.strip_prefix('>') {
+                (Align::Right, d)
+            } else if let Some(d) = w.strip_prefix('<') {
+                (Align::Left, d)
+            } else {
+                (Align::Left, w)
+            };
+            let width = digits
+                .parse::<usize>()
+                .map_err(|_| format!("invalid width in placeholder {{{}}}", spec))?;
+            (align, width)
+        }
+    };
+
+    Ok(Segment::Placeholder {
+        placeholder,
+        width,
+        align,
+    })
+}
+
+fn lookup_field<'a>(fields: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(value) = fields.get(path) {
+        return Some(value);
+    }
+    let mut parts = path.split('.');
+    let mut current = fields.get(parts.next()?)?;
+    for part in parts {
+        current = match current {
+            Value::Object(map) => map.get(part)?,
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn placeholder_value(placeholder: &Placeholder, record: &Record) -> String {
+    match placeholder {
+        Placeholder::Timestamp => record.timestamp.to_string(),
+        Placeholder::Level => record.level.to_string(),
+        Placeholder::Severity => record.level.otel_severity().to_string(),
+        Placeholder::SyslogSeverity => record.level.syslog_severity().to_string(),
+        Placeholder::App => record.app.to_string(),
+        Placeholder::SubApp => record.sub_app.unwrap_or("").to_string(),
+        Placeholder::Target => record.target.to_string(),
+        Placeholder::Message => record.message.to_string(),
+        Placeholder::Field(name) => match lookup_field(record.fields, name) {
+            Some(value @ (Value::Object(_) | Value::Array(_))) => value.to_string(),
+            Some(value) => fields::render_value(value),
+            None => String::new(),
+        },
+        Placeholder::Fields => {
+            let pairs = fields::render_pairs(record.fields);
+            if pairs.is_empty() {
+                pairs
+            } else {
+                format!(" {}", pairs)
+            }
+        }
+        Placeholder::Pid => std::process::id().to_string(),
+        Placeholder::Hostname => hostname().to_string(),
+        Placeholder::Seq => record.seq.to_string(),
+    }
+}
+
+fn colorize(placeholder: &Placeholder, record: &Record, value: String) -> ColoredString {
+    match placeholder {
+        Placeholder::Timestamp => value.bright_red(),
+        Placeholder::Level | Placeholder::Severity | Placeholder::SyslogSeverity => {
+            record.level.colorize(value)
+        }
+        Placeholder::App | Placeholder::SubApp | Placeholder::Target => value.cyan(),
+        Placeholder::Message => value.white(),
+        Placeholder::Fields => value.dimmed(),
+        _ => value.normal(),
+    }
+}
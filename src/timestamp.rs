@@ -0,0 +1,224 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::str::FromStr;
+
+/// How timestamps are rendered. `Default` keeps chrono's `Display` output.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimestampFormat {
+    #[default]
+    Default,
+    Rfc3339,
+    Rfc3339Millis,
+    Unix,
+    UnixMs,
+    Iso8601Local,
+    Strftime(String),
+}
+
+impl std::fmt::Display for TimestampFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TimestampFormat::Default => write!(f, "default"),
+            TimestampFormat::Rfc3339 => write!(f, "rfc3339"),
+            TimestampFormat::Rfc3339Millis => write!(f, "rfc3339_millis"),
+            TimestampFormat::Unix => write!(f, "unix"),
+            TimestampFormat::UnixMs => write!(f, "unix_ms"),
+            TimestampFormat::Iso8601Local => write!(f, "iso8601_local"),
+            TimestampFormat::Strftime(pattern) => write!(f, "{}", pattern),
+        }
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(TimestampFormat::Default),
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "rfc3339_millis" => Ok(TimestampFormat::Rfc3339Millis),
+            "unix" => Ok(TimestampFormat::Unix),
+            "unix_ms" => Ok(TimestampFormat::UnixMs),
+            "iso8601_local" => Ok(TimestampFormat::Iso8601Local),
+            _ => {
+                if StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+                    Err(format!("invalid timestamp format: {:?}", s))
+                } else {
+                    Ok(TimestampFormat::Strftime(s.to_string()))
+                }
+            }
+        }
+    }
+}
+
+impl Serialize for TimestampFormat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimestampFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<TimestampFormat>()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Timezone timestamps are rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timezone {
+    #[default]
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl std::fmt::Display for Timezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Timezone::Utc => write!(f, "utc"),
+            Timezone::Local => write!(f, "local"),
+            Timezone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utc" | "z" => Ok(Timezone::Utc),
+            "local" => Ok(Timezone::Local),
+            _ => parse_offset(s)
+                .map(Timezone::Fixed)
+                .ok_or_else(|| format!("invalid timezone: {:?}", s)),
+        }
+    }
+}
+
+impl Serialize for Timezone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timezone {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: String = String::deserialize(deserializer)?;
+        s.parse::<Timezone>().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parses `+02:00`, `-0530` or `+2` style offsets.
+fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let rest = &s[1..];
+    // The slicing below is by bytes.
+    if !rest.is_ascii() {
+        return None;
+    }
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    if hours.is_empty() || hours.len() > 2 || minutes.len() > 2 {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Renders timestamps according to the configured format and timezone.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    pub format: TimestampFormat,
+    pub timezone: Timezone,
+}
+
+impl Clock {
+    pub fn localize(&self, now: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.timezone {
+            Timezone::Utc => now.fixed_offset(),
+            Timezone::Local => now.with_timezone(&Local).fixed_offset(),
+            Timezone::Fixed(offset) => now.with_timezone(&offset),
+        }
+    }
+
+    /// Formats `now` using the configured format.
+    pub fn format(&self, now: DateTime<Utc>) -> String {
+        let local = self.localize(now);
+        match &self.format {
+            TimestampFormat::Default => match self.timezone {
+                Timezone::Utc => now.to_string(),
+                _ => local.to_string(),
+            },
+            TimestampFormat::Rfc3339 => local.to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimestampFormat::Rfc3339Millis => local.to_rfc3339_opts(SecondsFormat::Millis, true),
+            TimestampFormat::Unix => now.timestamp().to_string(),
+            TimestampFormat::UnixMs => now.timestamp_millis().to_string(),
+            TimestampFormat::Iso8601Local => local.format("%Y-%m-%dT%H:%M:%S%.3f").to_string(),
+            TimestampFormat::Strftime(pattern) => local.format(pattern).to_string(),
+        }
+    }
+
+    /// RFC 3339 timestamp in the configured timezone, used by structured formats.
+    pub fn rfc3339(&self, now: DateTime<Utc>) -> String {
+        self.localize(now)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+
+    /// Filename-safe timestamp in the configured timezone, used for archive names.
+    pub fn archive_stamp(&self, now: DateTime<Utc>) -> String {
+        self.localize(now).format("%Y-%m-%d_%H-%M-%S").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets() {
+        let table = [
+            ("+02:00", Some(7200)),
+            ("-0530", Some(-19800)),
+            ("+2", Some(7200)),
+            ("-11", Some(-39600)),
+            ("+00:30", Some(1800)),
+            ("+24:00", None),
+            ("+01:60", None),
+            ("+123", None),
+            ("02:00", None),
+            ("+", None),
+            ("+1é1", None),
+            ("-é", None),
+            ("+1:é", None),
+        ];
+        for (input, expected) in table {
+            assert_eq!(
+                parse_offset(input).map(|o| o.local_minus_utc()),
+                expected,
+                "{:?}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Reads a config file as JSON, or as TOML if it has a `.toml` extension.
+pub fn load_config_file(path: &Path) -> Result<serde_json::Value, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let is_toml = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+
+    let mut value = if is_toml {
+        let table: toml::Table = contents
+            .parse()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        serde_json::to_value(table).map_err(|e| e.to_string())?
+    } else {
+        serde_json::from_str(&contents).map_err(|e| e.to_string())?
+    };
+
+    if !value.is_object() {
+        return Err("expected a table of settings".to_string());
+    }
+    // A config file cannot point at another config file.
+    if let Some(map) = value.as_object_mut() {
+        map.remove("config_file");
+    }
+    Ok(value)
+}
+
+fn modified(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polls a file's mtime on a background thread and calls `on_change` once a
+/// change has settled for a full interval, so half-written files are not read.
+/// The thread exits once the watcher is dropped or stopped.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl ConfigWatcher {
+    pub fn spawn(
+        path: PathBuf,
+        interval: Duration,
+        on_change: impl Fn() -> bool + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let watched = path.clone();
+        let mut last = modified(&watched);
+        let mut pending = false;
+        let thread = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            let current = modified(&watched);
+            if current != last {
+                last = current;
+                pending = last.is_some();
+            } else if pending {
+                pending = false;
+                if !on_change() {
+                    return;
+                }
+            }
+        });
+        ConfigWatcher { path, stop, thread }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops the thread and waits for a reload in progress to finish.
+    pub fn stop(self) {
+        drop(self.stop);
+        let _ = self.thread.join();
+    }
+}